@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE audit_logs;
+DROP TYPE audit_action;
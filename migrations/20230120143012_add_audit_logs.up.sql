@@ -0,0 +1,21 @@
+-- Add up migration script here
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken'
+);
+
+CREATE TABLE audit_logs (
+	id SERIAL PRIMARY KEY,
+	actor_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+	action audit_action NOT NULL,
+	target TEXT,
+	ip_address TEXT,
+	details TEXT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX audit_logs_actor_id_idx ON audit_logs (actor_id);
+CREATE INDEX audit_logs_action_idx ON audit_logs (action);
+CREATE INDEX audit_logs_created_at_idx ON audit_logs (created_at);
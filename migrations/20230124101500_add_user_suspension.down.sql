@@ -0,0 +1,17 @@
+-- Add down migration script here
+ALTER TABLE users DROP COLUMN suspended_at;
+ALTER TABLE users DROP COLUMN suspended_until;
+ALTER TABLE users DROP COLUMN suspension_reason;
+
+/* Values can't be dropped from an enum, so recreate it without the new ones */
+DELETE FROM audit_logs
+WHERE action::text IN ('AccessLevelChange', 'Suspend', 'Unsuspend', 'DeleteUser', 'AnonymizeUser');
+ALTER TYPE audit_action RENAME TO audit_action_old;
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken'
+);
+ALTER TABLE audit_logs ALTER COLUMN action TYPE audit_action USING action::text::audit_action;
+DROP TYPE audit_action_old;
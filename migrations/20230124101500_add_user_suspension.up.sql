@@ -0,0 +1,12 @@
+-- Add up migration script here
+/* A user is suspended when suspended_at is set, indefinitely (banned) if suspended_until is null */
+ALTER TABLE users ADD COLUMN suspended_at timestamptz;
+ALTER TABLE users ADD COLUMN suspended_until timestamptz;
+ALTER TABLE users ADD COLUMN suspension_reason text;
+
+/* New audit log actions for account management */
+ALTER TYPE audit_action ADD VALUE 'AccessLevelChange';
+ALTER TYPE audit_action ADD VALUE 'Suspend';
+ALTER TYPE audit_action ADD VALUE 'Unsuspend';
+ALTER TYPE audit_action ADD VALUE 'DeleteUser';
+ALTER TYPE audit_action ADD VALUE 'AnonymizeUser';
@@ -0,0 +1,20 @@
+-- Add down migration script here
+ALTER TABLE refresh_tokens DROP COLUMN session_id;
+DROP TABLE sessions;
+
+/* Values can't be dropped from an enum, so recreate it without the new ones */
+DELETE FROM audit_logs WHERE action::text IN ('Logout', 'RevokeSession', 'RefreshTokenReuse');
+ALTER TYPE audit_action RENAME TO audit_action_old;
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken',
+	'AccessLevelChange',
+	'Suspend',
+	'Unsuspend',
+	'DeleteUser',
+	'AnonymizeUser'
+);
+ALTER TABLE audit_logs ALTER COLUMN action TYPE audit_action USING action::text::audit_action;
+DROP TYPE audit_action_old;
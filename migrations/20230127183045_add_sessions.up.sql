@@ -0,0 +1,22 @@
+-- Add up migration script here
+CREATE TABLE sessions (
+	id TEXT PRIMARY KEY,
+	user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	ip_address TEXT,
+	user_agent TEXT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+	last_used_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+	expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+	revoked_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions (user_id);
+
+/* Every refresh token now belongs to a session. Tokens issued before can't be tied to one, so they're dropped */
+DELETE FROM refresh_tokens;
+ALTER TABLE refresh_tokens
+	ADD COLUMN session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE;
+
+ALTER TYPE audit_action ADD VALUE 'Logout';
+ALTER TYPE audit_action ADD VALUE 'RevokeSession';
+ALTER TYPE audit_action ADD VALUE 'RefreshTokenReuse';
@@ -0,0 +1,24 @@
+-- Add up migration script here
+ALTER TABLE users ADD COLUMN email_verified_at timestamptz;
+
+CREATE TYPE user_token_kind AS ENUM (
+	'EmailVerification',
+	'PasswordReset'
+);
+
+/* Single-use tokens mailed to users, only a hash of the token is stored */
+CREATE TABLE user_tokens (
+	id SERIAL PRIMARY KEY,
+	user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	kind user_token_kind NOT NULL,
+	token_hash TEXT NOT NULL UNIQUE,
+	expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+	used_at TIMESTAMP WITH TIME ZONE,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX user_tokens_user_id_idx ON user_tokens (user_id);
+
+ALTER TYPE audit_action ADD VALUE 'EmailVerified';
+ALTER TYPE audit_action ADD VALUE 'PasswordResetRequested';
+ALTER TYPE audit_action ADD VALUE 'PasswordReset';
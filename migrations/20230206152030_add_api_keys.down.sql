@@ -0,0 +1,27 @@
+-- Add down migration script here
+DROP TABLE api_keys;
+DROP TYPE api_key_scope;
+
+/* Values can't be dropped from an enum, so recreate it without the new ones */
+DELETE FROM audit_logs
+WHERE action::text IN ('CreateApiKey', 'RevokeApiKey');
+ALTER TYPE audit_action RENAME TO audit_action_old;
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken',
+	'AccessLevelChange',
+	'Suspend',
+	'Unsuspend',
+	'DeleteUser',
+	'AnonymizeUser',
+	'Logout',
+	'RevokeSession',
+	'RefreshTokenReuse',
+	'EmailVerified',
+	'PasswordResetRequested',
+	'PasswordReset'
+);
+ALTER TABLE audit_logs ALTER COLUMN action TYPE audit_action USING action::text::audit_action;
+DROP TYPE audit_action_old;
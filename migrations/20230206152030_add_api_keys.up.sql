@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TYPE api_key_scope AS ENUM (
+	'ReadOnly',
+	'Write'
+);
+
+/* Personal API keys for scripts and bots, only a hash of the key is stored */
+CREATE TABLE api_keys (
+	id TEXT PRIMARY KEY,
+	user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	name TEXT NOT NULL,
+	prefix TEXT NOT NULL,
+	key_hash TEXT NOT NULL UNIQUE,
+	scope api_key_scope NOT NULL,
+	expires_at TIMESTAMP WITH TIME ZONE,
+	last_used_at TIMESTAMP WITH TIME ZONE,
+	revoked_at TIMESTAMP WITH TIME ZONE,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX api_keys_user_id_idx ON api_keys (user_id);
+
+ALTER TYPE audit_action ADD VALUE 'CreateApiKey';
+ALTER TYPE audit_action ADD VALUE 'RevokeApiKey';
@@ -0,0 +1,25 @@
+-- Add up migration script here
+/* Logins started at an OpenID Connect provider that haven't come back yet */
+CREATE TABLE oidc_states (
+	state TEXT PRIMARY KEY,
+	provider TEXT NOT NULL,
+	nonce TEXT NOT NULL,
+	code_verifier TEXT NOT NULL,
+	expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+/* Accounts at OpenID Connect providers that users log in with */
+CREATE TABLE user_identities (
+	provider TEXT NOT NULL,
+	subject TEXT NOT NULL,
+	user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	email TEXT,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+	last_login_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL,
+	PRIMARY KEY (provider, subject)
+);
+
+CREATE INDEX user_identities_user_id_idx ON user_identities (user_id);
+
+ALTER TYPE audit_action ADD VALUE 'LinkIdentity';
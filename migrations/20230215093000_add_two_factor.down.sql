@@ -0,0 +1,42 @@
+-- Add down migration script here
+DROP TABLE recovery_codes;
+ALTER TABLE users DROP COLUMN totp_last_step;
+ALTER TABLE users DROP COLUMN totp_enabled_at;
+ALTER TABLE users DROP COLUMN totp_secret;
+
+/* Values can't be dropped from an enum, so recreate them without the new ones */
+DELETE FROM user_tokens
+WHERE kind::text = 'TwoFactorChallenge';
+ALTER TYPE user_token_kind RENAME TO user_token_kind_old;
+CREATE TYPE user_token_kind AS ENUM (
+	'EmailVerification',
+	'PasswordReset'
+);
+ALTER TABLE user_tokens ALTER COLUMN kind TYPE user_token_kind USING kind::text::user_token_kind;
+DROP TYPE user_token_kind_old;
+
+DELETE FROM audit_logs
+WHERE action::text IN ('TwoFactorEnabled', 'TwoFactorDisabled', 'TwoFactorFailed', 'RecoveryCodesRegenerated');
+ALTER TYPE audit_action RENAME TO audit_action_old;
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken',
+	'AccessLevelChange',
+	'Suspend',
+	'Unsuspend',
+	'DeleteUser',
+	'AnonymizeUser',
+	'Logout',
+	'RevokeSession',
+	'RefreshTokenReuse',
+	'EmailVerified',
+	'PasswordResetRequested',
+	'PasswordReset',
+	'CreateApiKey',
+	'RevokeApiKey',
+	'LinkIdentity'
+);
+ALTER TABLE audit_logs ALTER COLUMN action TYPE audit_action USING action::text::audit_action;
+DROP TYPE audit_action_old;
@@ -0,0 +1,23 @@
+-- Add up migration script here
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
+ALTER TABLE users ADD COLUMN totp_enabled_at TIMESTAMP WITH TIME ZONE;
+/* Time step of the last accepted code, so a code can't be used twice */
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
+
+/* Single-use codes to log in without the authenticator app, only a hash is stored */
+CREATE TABLE recovery_codes (
+	id SERIAL PRIMARY KEY,
+	user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+	code_hash TEXT NOT NULL,
+	used_at TIMESTAMP WITH TIME ZONE,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
+
+ALTER TYPE user_token_kind ADD VALUE 'TwoFactorChallenge';
+
+ALTER TYPE audit_action ADD VALUE 'TwoFactorEnabled';
+ALTER TYPE audit_action ADD VALUE 'TwoFactorDisabled';
+ALTER TYPE audit_action ADD VALUE 'TwoFactorFailed';
+ALTER TYPE audit_action ADD VALUE 'RecoveryCodesRegenerated';
@@ -0,0 +1,33 @@
+-- Add down migration script here
+DROP TABLE persisted_queries;
+
+/* Values can't be dropped from an enum, so recreate it without the new ones */
+DELETE FROM audit_logs
+WHERE action::text IN ('RegisterPersistedQuery', 'DeletePersistedQuery');
+ALTER TYPE audit_action RENAME TO audit_action_old;
+CREATE TYPE audit_action AS ENUM (
+	'Login',
+	'LoginFailed',
+	'Register',
+	'RefreshToken',
+	'AccessLevelChange',
+	'Suspend',
+	'Unsuspend',
+	'DeleteUser',
+	'AnonymizeUser',
+	'Logout',
+	'RevokeSession',
+	'RefreshTokenReuse',
+	'EmailVerified',
+	'PasswordResetRequested',
+	'PasswordReset',
+	'CreateApiKey',
+	'RevokeApiKey',
+	'LinkIdentity',
+	'TwoFactorEnabled',
+	'TwoFactorDisabled',
+	'TwoFactorFailed',
+	'RecoveryCodesRegenerated'
+);
+ALTER TABLE audit_logs ALTER COLUMN action TYPE audit_action USING action::text::audit_action;
+DROP TYPE audit_action_old;
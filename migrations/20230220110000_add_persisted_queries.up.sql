@@ -0,0 +1,12 @@
+-- Add up migration script here
+/* Queries clients can run by their SHA-256 hash instead of sending the whole document */
+CREATE TABLE persisted_queries (
+	hash TEXT PRIMARY KEY,
+	query TEXT NOT NULL,
+	/* Registered by an admin, so it's accepted when only allow-listed queries are */
+	allow_listed BOOLEAN DEFAULT FALSE NOT NULL,
+	created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW() NOT NULL
+);
+
+ALTER TYPE audit_action ADD VALUE 'RegisterPersistedQuery';
+ALTER TYPE audit_action ADD VALUE 'DeletePersistedQuery';
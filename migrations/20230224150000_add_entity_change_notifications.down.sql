@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TRIGGER releases_notify_change ON releases;
+DROP TRIGGER artists_notify_change ON artists;
+DROP TRIGGER songs_notify_change ON songs;
+DROP FUNCTION notify_entity_change();
@@ -0,0 +1,19 @@
+-- Add up migration script here
+/* Tells every listening server instance about new and changed entities, this feeds the GraphQL subscriptions */
+CREATE FUNCTION notify_entity_change() RETURNS trigger AS $$
+BEGIN
+	PERFORM pg_notify('entity_changes', json_build_object(
+		'kind', TG_ARGV[0],
+		'operation', TG_OP,
+		'id', NEW.id
+	)::text);
+	RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER songs_notify_change AFTER INSERT OR UPDATE ON songs
+	FOR EACH ROW EXECUTE FUNCTION notify_entity_change('Song');
+CREATE TRIGGER artists_notify_change AFTER INSERT OR UPDATE ON artists
+	FOR EACH ROW EXECUTE FUNCTION notify_entity_change('Artist');
+CREATE TRIGGER releases_notify_change AFTER INSERT OR UPDATE ON releases
+	FOR EACH ROW EXECUTE FUNCTION notify_entity_change('Release');
@@ -1,49 +1,241 @@
+use super::subscription::SubscriptionRoot;
 use crate::{
-    constants::AUTH_DEFAULT_ACCESS_LEVEL,
+    constants::{AUTH_DEFAULT_ACCESS_LEVEL, QUERY_COST_LIST_SIZE, RATE_LIMIT_LOGIN_MUTATIONS},
     controllers::page::{Page, PageInfo},
-    database::user::LoginResponse,
+    database::{
+        two_factor::SecondFactor,
+        user::{LoginResponse, OidcLink},
+    },
     models::{
+        api_key::{ApiKey, ApiKeyScope, CreatedApiKey, NewApiKey},
         artist::Artist,
+        audit_log::{AuditAction, AuditLog, NewAuditLog},
+        oidc::{OidcAuthorization, OidcLogin},
+        persisted_query::PersistedQuery,
         refresh_token::{RefreshTokenInput, RefreshedToken},
+        session::Session,
         song::{NewSong, Song},
-        user::{Login, Register, User},
+        two_factor::TwoFactorSetup,
+        user::{AccessLevel, Login, Register, ResetPassword, SuspendUser, User, VerifyTwoFactor},
         Name,
     },
-    utils::{error::Error, middleware::Claims},
+    utils::{
+        config::{self, Config},
+        error::Error,
+        mailer::Mailer,
+        metrics::Metrics,
+        middleware::{rate_limit_key, Claims, ClientInfo},
+        oidc::OidcClient,
+        query_limits::{list_cost, lookup_cost, page_size, QueryLimits},
+        rate_limit::{Budget, RateLimited, RateLimiter},
+        replicas::Replicas,
+        response_cache::{etag, matches_etag, ResponseCache},
+        token::hash_token,
+    },
+};
+use async_graphql::{
+    http::graphiql_source,
+    parser::types::{
+        DocumentOperations, ExecutableDocument, OperationType, Selection, SelectionSet,
+    },
+    Context, ErrorExtensionValues, Object, Schema, ServerError,
+};
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Method, Request, Response, StatusCode,
 };
-use async_graphql::{http::graphiql_source, Context, EmptySubscription, Object, Schema};
-use hyper::{Body, Request, Response};
 use routerify::prelude::*;
-use sqlx::PgPool;
-use std::{io, sync::Arc};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    PgPool,
+};
+use std::{collections::HashSet, io, sync::Arc, time::Instant};
+use tracing::error;
 
 pub async fn graphiql(_: Request<Body>) -> Result<Response<Body>, io::Error> {
     let html = graphiql_source("/graphql", None);
     Ok(Response::new(Body::from(html)))
 }
 
+pub type AppSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// Runs GraphQL requests, sent as a JSON body with POST or as URL parameters with GET.
+///
+/// Only queries can be sent with GET. Their responses carry an `ETag` and `Cache-Control`,
+/// and responses to anonymous queries are kept in the [`ResponseCache`] until a write.
 pub async fn graphql(req: Request<Body>) -> Result<Response<Body>, io::Error> {
-    let schema = &*req
-        .data::<Arc<Schema<QueryRoot, MutationRoot, EmptySubscription>>>()
-        .unwrap()
-        .clone();
+    if hyper_tungstenite::is_upgrade_request(&req) {
+        return super::subscription::graphql_ws(req).await;
+    }
+
+    let schema = &*req.data::<Arc<AppSchema>>().unwrap().clone();
     let db = req.data::<PgPool>().unwrap().clone();
+    let mailer = req.data::<Arc<Mailer>>().unwrap().clone();
+    let oidc = req.data::<Arc<OidcClient>>().unwrap().clone();
+    let limiter = req.data::<Arc<RateLimiter>>().unwrap().clone();
+    let cache = req.data::<Arc<ResponseCache>>().unwrap().clone();
+    let replicas = req.data::<Arc<Replicas>>().unwrap().clone();
+    let metrics = req.data::<Arc<Metrics>>().unwrap().clone();
+    let config = req.data::<Config>().unwrap().clone();
     let claims = req.context::<Claims>();
-    let mut request = deserialize_body(req.into_body()).await?;
+    let api_key = req.context::<ApiKey>();
+    let client = ClientInfo::from_request(&req);
+    let key = rate_limit_key(&req);
+    let is_get = req.method() == Method::GET;
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut request = if is_get {
+        match async_graphql::http::parse_query_string(req.uri().query().unwrap_or_default()) {
+            Ok(request) => request,
+            Err(err) => {
+                let err = ServerError::new(format!("INVALID_REQUEST: {err}"), None);
+                return Ok(status_response(StatusCode::BAD_REQUEST, error_body(err)));
+            }
+        }
+    } else {
+        deserialize_body(req.into_body()).await?
+    };
+    let is_admin = matches!(&claims, Some(claims) if claims.access_level == AccessLevel::Admin);
+    let allow_listed =
+        match resolve_persisted_query(&mut request, &config.persisted_queries, is_admin, &db).await
+        {
+            Ok(allow_listed) => allow_listed,
+            Err(err) => return Ok(Response::new(Body::from(error_body(err)))),
+        };
+    let operation = selected_operation(&request);
+    let operation_type = operation.as_ref().map(|operation| operation.ty);
+    let is_query = operation_type == Some(OperationType::Query);
+    let is_mutation = operation_type == Some(OperationType::Mutation);
+
+    // GET requests can be repeated and prefetched, so they mustn't change anything.
+    if is_get && is_mutation {
+        let err = ServerError::new("MUTATIONS_REQUIRE_POST", None);
+        let mut res = status_response(StatusCode::METHOD_NOT_ALLOWED, error_body(err));
+        res.headers_mut()
+            .insert(header::ALLOW, HeaderValue::from_static("POST"));
+        return Ok(res);
+    }
+    if let Err(limited) = check_budgets(&limiter, &key, &client, operation.as_ref()) {
+        return Ok(limited.response(error_body(ServerError::new("RATE_LIMITED", None))));
+    }
+
+    let is_anonymous = claims.is_none() && api_key.is_none();
+    let cache_control = if is_anonymous {
+        format!("public, max-age={}", config.response_cache.max_age)
+    } else {
+        "private, no-cache".to_string()
+    };
+    let cache_key =
+        (is_anonymous && is_query && cache.enabled()).then(|| ResponseCache::key(&request));
+    if let Some(body) = cache_key.as_deref().and_then(|key| cache.get(key)) {
+        if is_get {
+            return Ok(get_response(if_none_match.as_ref(), body, &cache_control));
+        }
+        return Ok(Response::new(Body::from(body)));
+    }
+    let generation = cache.generation();
+
     if claims.is_some() {
         request = request.data(claims.unwrap());
     }
-    let response = schema.execute(request.data(db)).await;
+    if let Some(api_key) = api_key {
+        if api_key.scope == ApiKeyScope::ReadOnly && is_mutation {
+            let err = ServerError::new("API_KEY_READ_ONLY", None);
+            return Ok(Response::new(Body::from(error_body(err))));
+        }
+        request = request.data(api_key);
+    }
+    // Queries read from a replica, unless the client has to see a change it just made.
+    let db = match is_query {
+        true => replicas.read_pool(&key).unwrap_or(db),
+        false => db,
+    };
+    let start = Instant::now();
+    let response = schema
+        .execute(
+            request
+                .data(db)
+                .data(mailer)
+                .data(oidc)
+                .data(limiter)
+                .data(metrics.clone())
+                .data(config)
+                .data(client),
+        )
+        .await;
+    // Clients pick the names of their operations, only those of the allow-list are kept
+    // so the number of series stays bounded.
+    metrics.record_graphql(
+        operation.as_ref().map(|operation| {
+            let name = operation.name.as_deref().filter(|_| allow_listed);
+            (operation.ty, name)
+        }),
+        &response,
+        start.elapsed(),
+    );
+    let body = serde_json::to_string(&response).unwrap();
 
-    Ok(Response::new(Body::from(
-        serde_json::to_string(&response).unwrap(),
-    )))
+    if is_mutation {
+        cache.clear();
+        replicas.record_write(&key);
+    }
+    let is_cacheable = response.errors.is_empty() && response.http_headers.is_empty();
+    if let (Some(key), true) = (cache_key, is_cacheable) {
+        cache.insert(key, body.clone(), generation);
+    }
+
+    let mut res = if is_get && is_cacheable {
+        get_response(if_none_match.as_ref(), body, &cache_control)
+    } else {
+        Response::new(Body::from(body))
+    };
+    // Resolvers set `Retry-After` when a client is locked out.
+    if response.http_headers.contains_key(header::RETRY_AFTER) {
+        *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+    }
+    res.headers_mut().extend(response.http_headers);
+
+    Ok(res)
+}
+
+/// Responds to a GET query with the body and its `ETag`, or with `304 Not Modified`
+/// if the client already has that body.
+fn get_response(
+    if_none_match: Option<&HeaderValue>,
+    body: String,
+    cache_control: &str,
+) -> Response<Body> {
+    let etag = etag(&body);
+    let not_modified = if_none_match
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| matches_etag(value, &etag));
+
+    let mut res = if not_modified {
+        status_response(StatusCode::NOT_MODIFIED, String::new())
+    } else {
+        Response::new(Body::from(body))
+    };
+    let headers = res.headers_mut();
+    headers.insert(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_str(cache_control).unwrap(),
+    );
+    // Responses to anonymous requests mustn't be reused for logged in users, or the other way around.
+    headers.insert(header::VARY, HeaderValue::from_static("Authorization"));
+    res
+}
+
+fn status_response(status: StatusCode, body: String) -> Response<Body> {
+    let mut res = Response::new(Body::from(body));
+    *res.status_mut() = status;
+    res
 }
 
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
+    #[graphql(complexity = "lookup_cost(child_complexity)")]
     async fn song<'ctx>(
         &self,
         context: &Context<'ctx>,
@@ -88,6 +280,7 @@ impl QueryRoot {
         crate::database::song::get_song(&options, db).await
     }
 
+    #[graphql(complexity = "lookup_cost(child_complexity)")]
     async fn artist<'ctx>(
         &self,
         context: &Context<'ctx>,
@@ -126,6 +319,7 @@ impl QueryRoot {
         crate::database::artist::get_artist(&options, db).await
     }
 
+    #[graphql(complexity = "list_cost(page_size(per_page), child_complexity)")]
     async fn page<'ctx>(&self, page: Option<i32>, per_page: Option<i32>) -> Result<Page, Error> {
         let page_info = PageInfo {
             total: 0,
@@ -149,6 +343,7 @@ impl QueryRoot {
         Ok(Page { page_info })
     }
 
+    #[graphql(complexity = "lookup_cost(child_complexity)")]
     async fn user<'ctx>(
         &self,
         context: &Context<'ctx>,
@@ -174,6 +369,99 @@ impl QueryRoot {
 
         crate::database::user::get_user(&options, db).await
     }
+
+    /// Get audit log entries, newest first. Only available to admins.
+    #[graphql(complexity = "list_cost(page_size(per_page), child_complexity)")]
+    #[allow(clippy::too_many_arguments)]
+    async fn audit_logs<'ctx>(
+        &self,
+        context: &Context<'ctx>,
+        actor_id: Option<String>,
+        action: Option<AuditAction>,
+        target: Option<String>,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+        page: Option<i32>,
+        per_page: Option<i32>,
+    ) -> Result<Vec<AuditLog>, Error> {
+        require_admin(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let options = crate::models::audit_log::Options {
+            actor_id,
+            action,
+            target,
+            since,
+            until,
+            page,
+            per_page,
+        };
+
+        crate::database::audit_log::get_audit_logs(&options, db).await
+    }
+
+    /// Get the active sessions of a user, defaults to the requesting user.
+    /// Only admins can list the sessions of other users.
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
+    async fn sessions<'ctx>(
+        &self,
+        context: &Context<'ctx>,
+        user_id: Option<String>,
+    ) -> Result<Vec<Session>, Error> {
+        let claims = require_user(context)?;
+        let user_id = user_id.unwrap_or_else(|| claims.ulid.clone());
+        require_self_or_admin(claims, &user_id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let options = crate::models::session::Options {
+            id: None,
+            user_id: Some(user_id),
+            active: true,
+        };
+
+        crate::database::session::get_sessions(&options, db).await
+    }
+
+    /// Names of the OpenID Connect providers users can log in with.
+    async fn oidc_providers(&self, context: &Context<'_>) -> Vec<String> {
+        context.data_unchecked::<Arc<OidcClient>>().provider_names()
+    }
+
+    /// Get the active API keys of a user, defaults to the requesting user.
+    /// Only admins can list the API keys of other users.
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
+    async fn api_keys<'ctx>(
+        &self,
+        context: &Context<'ctx>,
+        user_id: Option<String>,
+    ) -> Result<Vec<ApiKey>, Error> {
+        let claims = require_user(context)?;
+        let user_id = user_id.unwrap_or_else(|| claims.ulid.clone());
+        require_self_or_admin(claims, &user_id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let options = crate::models::api_key::Options {
+            id: None,
+            user_id: Some(user_id),
+            active: true,
+        };
+
+        crate::database::api_key::get_api_keys(&options, db).await
+    }
+
+    /// Get the persisted queries, optionally only those that are or aren't allow-listed.
+    /// Only available to admins.
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
+    async fn persisted_queries(
+        &self,
+        context: &Context<'_>,
+        allow_listed: Option<bool>,
+    ) -> Result<Vec<PersistedQuery>, Error> {
+        require_admin(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        crate::database::persisted_query::get_persisted_queries(allow_listed, db).await
+    }
 }
 
 pub struct MutationRoot;
@@ -201,8 +489,202 @@ impl MutationRoot {
         let password = input.password;
         let email = input.email;
         let username = input.username;
+        let attempted = email
+            .clone()
+            .or_else(|| username.clone())
+            .unwrap_or_default();
+        let client = context.data_opt::<ClientInfo>();
+        let lockout_key = format!(
+            "login:{}:{}",
+            client.map(|client| client.ip_address()).unwrap_or_default(),
+            attempted.to_lowercase()
+        );
+
+        let result = match check_lockout(context, &lockout_key) {
+            Ok(()) => {
+                let result = crate::database::user::login(
+                    email,
+                    username,
+                    password,
+                    client.map(|client| client.ip_address()),
+                    client.and_then(|client| client.user_agent.clone()),
+                    context.data_unchecked::<Config>(),
+                    db,
+                )
+                .await;
+                record_attempt(context, &lockout_key, result.is_ok());
+                record_auth(context, "login", result.is_ok());
+                result
+            }
+            Err(err) => Err(err),
+        };
+        match &result {
+            // Logins waiting for their second factor are audited once it's verified.
+            Ok(response) if response.token.is_none() => {}
+            Ok(response) => {
+                let user_id = response.user.id.to_string();
+                let entry = NewAuditLog::new(AuditAction::Login)
+                    .actor_id(user_id.clone())
+                    .target(user_id);
+                audit(context, entry).await;
+            }
+            Err(err) => {
+                let entry = NewAuditLog::new(AuditAction::LoginFailed)
+                    .target(attempted)
+                    .details(err.message.clone());
+                audit(context, entry).await;
+            }
+        }
+
+        result
+    }
+
+    /// Start logging in with an OpenID Connect provider. Send the user to the returned URL,
+    /// the provider redirects them back with the `code` and `state` to pass to `oidcLogin`.
+    async fn oidc_authorize(
+        &self,
+        context: &Context<'_>,
+        provider: String,
+    ) -> Result<OidcAuthorization, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let oidc = context.data_unchecked::<Arc<OidcClient>>();
 
-        crate::database::user::login(email, username, password, db).await
+        let authorization = oidc.authorize(&provider).await?;
+        crate::database::oidc::create_oidc_state(&provider, &authorization, db).await?;
+
+        Ok(OidcAuthorization {
+            url: authorization.url,
+            state: authorization.state,
+        })
+    }
+
+    /// Finish logging in with an OpenID Connect provider. Creates a user on the first login,
+    /// or links the existing user with the same verified email address.
+    async fn oidc_login(
+        &self,
+        context: &Context<'_>,
+        input: OidcLogin,
+    ) -> Result<LoginResponse, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let oidc = context.data_unchecked::<Arc<OidcClient>>();
+        let client = context.data_opt::<ClientInfo>();
+
+        let state = crate::database::oidc::consume_oidc_state(&input.state, db).await?;
+        let result = match oidc
+            .exchange_code(
+                &state.provider,
+                &input.code,
+                &state.code_verifier,
+                &state.nonce,
+            )
+            .await
+        {
+            Ok(claims) => crate::database::user::oidc_login(
+                &state.provider,
+                &claims,
+                client.map(|client| client.ip_address()),
+                client.and_then(|client| client.user_agent.clone()),
+                context.data_unchecked::<Config>(),
+                db,
+            )
+            .await
+            .map_err(|err| (err, Some(claims.sub))),
+            Err(err) => Err((err, None)),
+        };
+        record_auth(context, "oidc_login", result.is_ok());
+
+        match result {
+            Ok((response, link)) => {
+                let user_id = response.user.id.to_string();
+                let mut actions = match link {
+                    OidcLink::Existing => vec![],
+                    OidcLink::Linked => vec![AuditAction::LinkIdentity],
+                    OidcLink::Created => vec![AuditAction::Register],
+                };
+                // Logins waiting for their second factor are audited once it's verified.
+                if response.token.is_some() {
+                    actions.push(AuditAction::Login);
+                }
+                for action in actions {
+                    let entry = NewAuditLog::new(action)
+                        .actor_id(user_id.clone())
+                        .target(user_id.clone())
+                        .details(format!("oidc:{}", state.provider));
+                    audit(context, entry).await;
+                }
+
+                Ok(response)
+            }
+            Err((err, subject)) => {
+                let attempted = subject
+                    .map(|subject| format!("{}:{subject}", state.provider))
+                    .unwrap_or_else(|| state.provider.clone());
+                let entry = NewAuditLog::new(AuditAction::LoginFailed)
+                    .target(attempted)
+                    .details(err.message.clone());
+                audit(context, entry).await;
+
+                Err(err)
+            }
+        }
+    }
+
+    /// Finish a login of a user with two-factor authentication. A wrong code means
+    /// logging in again.
+    async fn verify_two_factor(
+        &self,
+        context: &Context<'_>,
+        input: VerifyTwoFactor,
+    ) -> Result<LoginResponse, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let client = context.data_opt::<ClientInfo>();
+
+        let user = crate::database::user::consume_two_factor_token(&input.token, db).await?;
+        let user_id = user.id.to_string();
+        // A password is enough to get new challenges, so guesses are counted per user.
+        let lockout_key = format!("two_factor:{user_id}");
+
+        let result = match check_lockout(context, &lockout_key) {
+            Ok(()) => {
+                let result = crate::database::user::verify_two_factor_login(
+                    user,
+                    &input.code,
+                    client.map(|client| client.ip_address()),
+                    client.and_then(|client| client.user_agent.clone()),
+                    context.data_unchecked::<Config>(),
+                    db,
+                )
+                .await;
+                record_attempt(context, &lockout_key, result.is_ok());
+                record_auth(context, "two_factor", result.is_ok());
+                result
+            }
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok((response, second_factor)) => {
+                let details = match second_factor {
+                    SecondFactor::Totp => "totp",
+                    SecondFactor::RecoveryCode => "recovery code",
+                };
+                let entry = NewAuditLog::new(AuditAction::Login)
+                    .actor_id(user_id.clone())
+                    .target(user_id)
+                    .details(details.to_string());
+                audit(context, entry).await;
+
+                Ok(response)
+            }
+            Err(err) => {
+                let entry = NewAuditLog::new(AuditAction::TwoFactorFailed)
+                    .target(user_id)
+                    .details(err.message.clone());
+                audit(context, entry).await;
+
+                Err(err)
+            }
+        }
     }
 
     async fn register<'a>(&self, context: &Context<'a>, input: Register) -> Result<User, Error> {
@@ -211,8 +693,107 @@ impl MutationRoot {
         let password = input.password;
         let username = input.username;
 
-        crate::database::user::create_user(email, username, password, AUTH_DEFAULT_ACCESS_LEVEL, db)
-            .await
+        let user = crate::database::user::create_user(
+            email,
+            username,
+            password,
+            AUTH_DEFAULT_ACCESS_LEVEL,
+            db,
+        )
+        .await?;
+
+        let entry = NewAuditLog::new(AuditAction::Register)
+            .actor_id(user.id.to_string())
+            .target(user.id.to_string());
+        audit(context, entry).await;
+
+        // The account is usable either way, the mail can be sent again later.
+        if let Err(err) = send_email_verification(context, &user).await {
+            error!("Failed to send verification mail to {}: {err}", user.id);
+        }
+
+        Ok(user)
+    }
+
+    /// Send the email verification mail to the requesting user again.
+    async fn resend_verification_email(&self, context: &Context<'_>) -> Result<bool, Error> {
+        let claims = require_user(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let options = crate::models::user::Options {
+            id: Some(claims.ulid.clone()),
+            email: None,
+            page: None,
+            per_page: None,
+        };
+        let user = crate::database::user::get_user(&options, db).await?;
+
+        if user.email_verified_at.is_some() {
+            return Err(Error::new(
+                "EMAIL_ALREADY_VERIFIED",
+                StatusCode::BAD_REQUEST,
+            ));
+        }
+
+        send_email_verification(context, &user).await?;
+
+        Ok(true)
+    }
+
+    /// Confirm an email address with the token from the verification mail.
+    async fn verify_email(&self, context: &Context<'_>, token: String) -> Result<User, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let user = crate::database::user::verify_email(&token, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::EmailVerified)
+            .actor_id(user.id.to_string())
+            .target(user.id.to_string());
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Mail a password reset link to the given address.
+    ///
+    /// Always succeeds, so it can't be used to find out which emails are registered.
+    async fn request_password_reset(
+        &self,
+        context: &Context<'_>,
+        email: String,
+    ) -> Result<bool, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let mailer = context.data_unchecked::<Arc<Mailer>>();
+
+        let entry = NewAuditLog::new(AuditAction::PasswordResetRequested).target(email.clone());
+        audit(context, entry).await;
+
+        if let Some((user, token)) =
+            crate::database::user::create_password_reset(&email, db).await?
+        {
+            if let Err(err) = mailer.send_password_reset(&user.email, &token).await {
+                error!("Failed to send password reset mail to {}: {err}", user.id);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Set a new password with the token from the password reset mail.
+    /// Logs the user out everywhere.
+    async fn reset_password(
+        &self,
+        context: &Context<'_>,
+        input: ResetPassword,
+    ) -> Result<bool, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let user = crate::database::user::reset_password(&input.token, input.password, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::PasswordReset)
+            .actor_id(user.id.to_string())
+            .target(user.id.to_string());
+        audit(context, entry).await;
+
+        Ok(true)
     }
 
     async fn refresh_token(
@@ -222,12 +803,665 @@ impl MutationRoot {
     ) -> Result<RefreshedToken, Error> {
         let db = context.data_unchecked::<PgPool>();
         let refresh_token = input.token;
-        crate::database::user::refresh_token(refresh_token, db).await
+        let refreshed = match crate::database::user::refresh_token(
+            refresh_token.clone(),
+            context.data_unchecked::<Config>(),
+            db,
+        )
+        .await
+        {
+            Ok(refreshed) => {
+                record_auth(context, "refresh_token", true);
+                refreshed
+            }
+            Err(err) => {
+                record_auth(context, "refresh_token", false);
+                if err.message == "REFRESH_TOKEN_REUSED" {
+                    let reused =
+                        crate::database::user::get_refresh_token(&refresh_token, db).await?;
+                    let entry = NewAuditLog::new(AuditAction::RefreshTokenReuse)
+                        .actor_id(reused.user_id)
+                        .target(reused.session_id);
+                    audit(context, entry).await;
+                }
+
+                return Err(err);
+            }
+        };
+
+        let entry = NewAuditLog::new(AuditAction::RefreshToken)
+            .actor_id(refreshed.user_id.clone())
+            .target(refreshed.user_id.clone());
+        audit(context, entry).await;
+
+        Ok(refreshed)
+    }
+
+    /// Revoke a session, either the one the given refresh token belongs to or the one the
+    /// request was made with.
+    async fn logout(&self, context: &Context<'_>, token: Option<String>) -> Result<bool, Error> {
+        let db = context.data_unchecked::<PgPool>();
+        let session_id = match token {
+            Some(token) => {
+                crate::database::user::get_refresh_token(&token, db)
+                    .await?
+                    .session_id
+            }
+            None => require_user(context)?.sid.clone(),
+        };
+
+        let session = crate::database::session::revoke_session(&session_id, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::Logout)
+            .actor_id(session.user_id)
+            .target(session.id);
+        audit(context, entry).await;
+
+        Ok(true)
+    }
+
+    /// Revoke a session of the requesting user. Admins can revoke any session.
+    async fn revoke_session(&self, context: &Context<'_>, id: String) -> Result<Session, Error> {
+        let claims = require_user(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let session = crate::database::session::get_session(&id, db).await?;
+        require_self_or_admin(claims, &session.user_id)?;
+
+        let session = crate::database::session::revoke_session(&id, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::RevokeSession)
+            .actor_id(claims.ulid.clone())
+            .target(session.id.clone());
+        audit(context, entry).await;
+
+        Ok(session)
+    }
+
+    /// Create an API key for the requesting user. The key is only returned this once.
+    async fn create_api_key(
+        &self,
+        context: &Context<'_>,
+        input: NewApiKey,
+    ) -> Result<CreatedApiKey, Error> {
+        let claims = require_session(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let (api_key, key) =
+            crate::database::api_key::create_api_key(&claims.ulid, input, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::CreateApiKey)
+            .actor_id(claims.ulid.clone())
+            .target(api_key.id.clone())
+            .details(format!("{} ({:?})", api_key.name, api_key.scope));
+        audit(context, entry).await;
+
+        Ok(CreatedApiKey { key, api_key })
     }
+
+    /// Revoke an API key of the requesting user. Admins can revoke any key.
+    async fn revoke_api_key(&self, context: &Context<'_>, id: String) -> Result<ApiKey, Error> {
+        let claims = require_session(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let api_key = crate::database::api_key::get_api_key(&id, db).await?;
+        require_self_or_admin(claims, &api_key.user_id)?;
+
+        let api_key = crate::database::api_key::revoke_api_key(&id, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::RevokeApiKey)
+            .actor_id(claims.ulid.clone())
+            .target(api_key.id.clone());
+        audit(context, entry).await;
+
+        Ok(api_key)
+    }
+
+    /// Start enabling two-factor authentication for the requesting user.
+    /// It's only enabled once `confirmTwoFactor` is called with a code from the authenticator app.
+    async fn enable_two_factor(&self, context: &Context<'_>) -> Result<TwoFactorSetup, Error> {
+        let claims = require_session(context)?;
+        let config = context.data_unchecked::<Config>();
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = current_user(claims, db).await?;
+        let secret = crate::database::two_factor::start_two_factor(&user, db).await?;
+        let otpauth_url =
+            crate::utils::totp::otpauth_url(&secret, &config.two_factor.issuer, &user.username)?;
+
+        Ok(TwoFactorSetup {
+            secret,
+            otpauth_url,
+        })
+    }
+
+    /// Enable two-factor authentication with a code from the authenticator app. Returns the
+    /// recovery codes, which can't be retrieved again. All other sessions are logged out.
+    async fn confirm_two_factor(
+        &self,
+        context: &Context<'_>,
+        code: String,
+    ) -> Result<Vec<String>, Error> {
+        let claims = require_session(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = current_user(claims, db).await?;
+        let recovery_codes =
+            crate::database::two_factor::confirm_two_factor(&user, &code, db).await?;
+        crate::database::session::revoke_other_sessions(&claims.ulid, &claims.sid, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::TwoFactorEnabled)
+            .actor_id(claims.ulid.clone())
+            .target(claims.ulid.clone());
+        audit(context, entry).await;
+
+        Ok(recovery_codes)
+    }
+
+    /// Replace the recovery codes of the requesting user, takes a code from the authenticator
+    /// app or one of the old recovery codes.
+    async fn regenerate_recovery_codes(
+        &self,
+        context: &Context<'_>,
+        code: String,
+    ) -> Result<Vec<String>, Error> {
+        let claims = require_session(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = current_user(claims, db).await?;
+        crate::database::two_factor::verify_second_factor(&user, &code, db).await?;
+        let recovery_codes =
+            crate::database::two_factor::regenerate_recovery_codes(&claims.ulid, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::RecoveryCodesRegenerated)
+            .actor_id(claims.ulid.clone())
+            .target(claims.ulid.clone());
+        audit(context, entry).await;
+
+        Ok(recovery_codes)
+    }
+
+    /// Turn off two-factor authentication for the requesting user, takes a code from the
+    /// authenticator app or a recovery code. Not possible if their access level requires it.
+    async fn disable_two_factor(&self, context: &Context<'_>, code: String) -> Result<User, Error> {
+        let claims = require_session(context)?;
+        let config = context.data_unchecked::<Config>();
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = current_user(claims, db).await?;
+        if config.two_factor.required_for.contains(&user.access_level) {
+            return Err(Error::new("TWO_FACTOR_REQUIRED", StatusCode::BAD_REQUEST));
+        }
+
+        crate::database::two_factor::verify_second_factor(&user, &code, db).await?;
+        let user = crate::database::two_factor::disable_two_factor(&claims.ulid, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::TwoFactorDisabled)
+            .actor_id(claims.ulid.clone())
+            .target(claims.ulid.clone());
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Turn off two-factor authentication for a user who lost their authenticator app and
+    /// recovery codes. Only available to admins.
+    async fn reset_two_factor(&self, context: &Context<'_>, id: String) -> Result<User, Error> {
+        let claims = require_admin(context)?;
+        forbid_self(claims, &id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = crate::database::two_factor::disable_two_factor(&id, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::TwoFactorDisabled)
+            .actor_id(claims.ulid.clone())
+            .target(id)
+            .details("reset by admin".to_string());
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Add a query to the allow-list, clients can then also run it by its SHA-256 hash.
+    /// Only available to admins.
+    async fn register_persisted_query(
+        &self,
+        context: &Context<'_>,
+        query: String,
+    ) -> Result<PersistedQuery, Error> {
+        let claims = require_admin(context)?;
+        if async_graphql::parser::parse_query(&query).is_err() {
+            return Err(Error::new("INVALID_QUERY", StatusCode::BAD_REQUEST));
+        }
+
+        let db = context.data_unchecked::<PgPool>();
+        let persisted_query =
+            crate::database::persisted_query::register_persisted_query(&query, true, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::RegisterPersistedQuery)
+            .actor_id(claims.ulid.clone())
+            .target(persisted_query.hash.clone());
+        audit(context, entry).await;
+
+        Ok(persisted_query)
+    }
+
+    /// Remove a persisted query, whether it was allow-listed or registered by a client.
+    /// Only available to admins.
+    async fn delete_persisted_query(
+        &self,
+        context: &Context<'_>,
+        hash: String,
+    ) -> Result<PersistedQuery, Error> {
+        let claims = require_admin(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let persisted_query =
+            crate::database::persisted_query::delete_persisted_query(&hash, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::DeletePersistedQuery)
+            .actor_id(claims.ulid.clone())
+            .target(hash);
+        audit(context, entry).await;
+
+        Ok(persisted_query)
+    }
+
+    /// Change the access level of a user. Only available to admins.
+    async fn update_access_level(
+        &self,
+        context: &Context<'_>,
+        id: String,
+        access_level: AccessLevel,
+    ) -> Result<User, Error> {
+        let claims = require_admin(context)?;
+        forbid_self(claims, &id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = crate::database::user::update_access_level(&id, access_level, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::AccessLevelChange)
+            .actor_id(claims.ulid.clone())
+            .target(id)
+            .details(format!("{access_level:?}"));
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Suspend a user, or ban them if no end date is given. Only available to admins.
+    async fn suspend_user(&self, context: &Context<'_>, input: SuspendUser) -> Result<User, Error> {
+        let claims = require_admin(context)?;
+        forbid_self(claims, &input.id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user =
+            crate::database::user::suspend_user(&input.id, input.reason.clone(), input.until, db)
+                .await?;
+
+        let until = input
+            .until
+            .map(|until| until.to_rfc3339())
+            .unwrap_or_else(|| "indefinitely".to_string());
+        let entry = NewAuditLog::new(AuditAction::Suspend)
+            .actor_id(claims.ulid.clone())
+            .target(input.id)
+            .details(format!("{} (until {until})", input.reason));
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Lift the suspension of a user. Only available to admins.
+    async fn unsuspend_user(&self, context: &Context<'_>, id: String) -> Result<User, Error> {
+        let claims = require_admin(context)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let user = crate::database::user::unsuspend_user(&id, db).await?;
+
+        let entry = NewAuditLog::new(AuditAction::Unsuspend)
+            .actor_id(claims.ulid.clone())
+            .target(id);
+        audit(context, entry).await;
+
+        Ok(user)
+    }
+
+    /// Delete a user. With `anonymize` set the account is kept but stripped of all personal data.
+    /// Only available to admins.
+    async fn delete_user(
+        &self,
+        context: &Context<'_>,
+        id: String,
+        #[graphql(default = false)] anonymize: bool,
+    ) -> Result<bool, Error> {
+        let claims = require_admin(context)?;
+        forbid_self(claims, &id)?;
+
+        let db = context.data_unchecked::<PgPool>();
+        let action = if anonymize {
+            crate::database::user::anonymize_user(&id, db).await?;
+            AuditAction::AnonymizeUser
+        } else {
+            crate::database::user::delete_user(&id, db).await?;
+            AuditAction::DeleteUser
+        };
+
+        let entry = NewAuditLog::new(action)
+            .actor_id(claims.ulid.clone())
+            .target(id);
+        audit(context, entry).await;
+
+        Ok(true)
+    }
+}
+
+async fn send_email_verification(context: &Context<'_>, user: &User) -> Result<(), Error> {
+    let db = context.data_unchecked::<PgPool>();
+    let mailer = context.data_unchecked::<Arc<Mailer>>();
+
+    let token = crate::database::user::create_email_verification(&user.id.to_string(), db).await?;
+    mailer.send_email_verification(&user.email, &token).await
+}
+
+/// Loads the requesting user.
+async fn current_user(claims: &Claims, db: &PgPool) -> Result<User, Error> {
+    let options = crate::models::user::Options {
+        id: Some(claims.ulid.clone()),
+        email: None,
+        page: None,
+        per_page: None,
+    };
+
+    crate::database::user::get_user(&options, db).await
+}
+
+/// Returns the claims of the requesting user if they're logged in.
+fn require_user<'a>(context: &'a Context<'_>) -> Result<&'a Claims, Error> {
+    context
+        .data_opt::<Claims>()
+        .ok_or_else(|| Error::new("Not authorized", StatusCode::UNAUTHORIZED))
+}
+
+/// Returns the claims of the requesting user if they logged in, rather than using an API key.
+///
+/// Keeps a leaked API key from being used to mint more keys.
+fn require_session<'a>(context: &'a Context<'_>) -> Result<&'a Claims, Error> {
+    if context.data_opt::<ApiKey>().is_some() {
+        return Err(Error::new("API_KEY_NOT_ALLOWED", StatusCode::FORBIDDEN));
+    }
+
+    require_user(context)
+}
+
+/// Makes sure the requesting user either is the given user or an admin.
+fn require_self_or_admin(claims: &Claims, user_id: &str) -> Result<(), Error> {
+    if claims.ulid == user_id || claims.access_level == AccessLevel::Admin {
+        return Ok(());
+    }
+
+    Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED))
+}
+
+/// Returns the claims of the requesting user if they are an admin.
+fn require_admin<'a>(context: &'a Context<'_>) -> Result<&'a Claims, Error> {
+    match context.data_opt::<Claims>() {
+        Some(claims) if claims.access_level == AccessLevel::Admin => Ok(claims),
+        _ => Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED)),
+    }
+}
+
+/// Stops admins from locking themselves out by acting on their own account.
+fn forbid_self(claims: &Claims, id: &str) -> Result<(), Error> {
+    if claims.ulid == id {
+        return Err(Error::new("CANNOT_MODIFY_SELF", StatusCode::BAD_REQUEST));
+    }
+
+    Ok(())
+}
+
+/// Fails if the client is locked out after too many failed attempts, telling it when to retry.
+fn check_lockout(context: &Context<'_>, key: &str) -> Result<(), Error> {
+    let limiter = context.data_unchecked::<Arc<RateLimiter>>();
+
+    limiter.check_lockout(key).map_err(|limited| {
+        context.insert_http_header(header::RETRY_AFTER, limited.header_value());
+        Error::new("TOO_MANY_ATTEMPTS", StatusCode::TOO_MANY_REQUESTS)
+    })
+}
+
+fn record_attempt(context: &Context<'_>, key: &str, succeeded: bool) {
+    let limiter = context.data_unchecked::<Arc<RateLimiter>>();
+
+    if succeeded {
+        limiter.record_success(key);
+    } else {
+        limiter.record_failure(key);
+    }
+}
+
+fn record_auth(context: &Context<'_>, event: &str, succeeded: bool) {
+    context
+        .data_unchecked::<Arc<Metrics>>()
+        .record_auth(event, succeeded);
+}
+
+/// Records an entry in the audit log along with the client's address.
+///
+/// Failing to write the entry is logged but never fails the request itself.
+async fn audit(context: &Context<'_>, entry: NewAuditLog) {
+    let db = context.data_unchecked::<PgPool>();
+    let ip_address = context
+        .data_opt::<ClientInfo>()
+        .map(|client| client.ip_address());
+
+    if let Err(err) =
+        crate::database::audit_log::create_audit_log(entry.ip_address(ip_address), db).await
+    {
+        error!("Failed to write audit log entry: {err}");
+    }
+}
+
+pub fn make_schema(limits: &config::QueryLimits) -> AppSchema {
+    Schema::build(QueryRoot {}, MutationRoot, SubscriptionRoot)
+        .extension(QueryLimits::new(limits))
+        .extension(async_graphql::extensions::Tracing)
+        .finish()
+}
+
+/// Counts the request against the budgets of its operation type.
+fn check_budgets(
+    limiter: &RateLimiter,
+    key: &str,
+    client: &ClientInfo,
+    operation: Option<&SelectedOperation>,
+) -> Result<(), RateLimited> {
+    match operation {
+        Some(SelectedOperation {
+            ty: OperationType::Query,
+            ..
+        }) => limiter.check(Budget::Query, key),
+        Some(SelectedOperation {
+            ty: OperationType::Mutation,
+            fields,
+            ..
+        }) => {
+            limiter.check(Budget::Mutation, key)?;
+            // Guessing credentials doesn't need an account, so these are counted per IP address.
+            if fields
+                .iter()
+                .any(|field| RATE_LIMIT_LOGIN_MUTATIONS.contains(&field.as_str()))
+            {
+                limiter.check(Budget::Login, &format!("ip:{}", client.ip_address()))?;
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The operation of a request, as far as it's needed before executing it.
+pub struct SelectedOperation {
+    pub ty: OperationType,
+    /// Name in the document, `None` for an anonymous operation.
+    pub name: Option<String>,
+    /// Top-level fields.
+    pub fields: Vec<String>,
+}
+
+/// Returns the operation the request runs, `None` if the query doesn't parse.
+/// Those are let through, executing them reports the error.
+pub fn selected_operation(request: &async_graphql::Request) -> Option<SelectedOperation> {
+    let document = async_graphql::parser::parse_query(&request.query).ok()?;
+
+    let (name, operation) = match &document.operations {
+        DocumentOperations::Single(operation) => (None, operation),
+        DocumentOperations::Multiple(operations) => operations
+            .iter()
+            .find(|(name, _)| {
+                request
+                    .operation_name
+                    .as_deref()
+                    .is_none_or(|operation_name| name.as_str() == operation_name)
+            })
+            .map(|(name, operation)| (Some(name.to_string()), operation))?,
+    };
+
+    let mut fields = Vec::new();
+    collect_fields(
+        &document,
+        &operation.node.selection_set.node,
+        &mut HashSet::new(),
+        &mut fields,
+    );
+
+    Some(SelectedOperation {
+        ty: operation.node.ty,
+        name,
+        fields,
+    })
+}
+
+/// Collects the names of the fields in the selection set, looking into fragments.
+fn collect_fields<'a>(
+    document: &'a ExecutableDocument,
+    selection_set: &'a SelectionSet,
+    visited: &mut HashSet<&'a str>,
+    fields: &mut Vec<String>,
+) {
+    for selection in &selection_set.items {
+        match &selection.node {
+            Selection::Field(field) => fields.push(field.node.name.node.to_string()),
+            Selection::InlineFragment(fragment) => {
+                collect_fields(document, &fragment.node.selection_set.node, visited, fields)
+            }
+            Selection::FragmentSpread(spread) => {
+                let name = spread.node.fragment_name.node.as_str();
+                // Fragments that spread themselves are invalid, but they still parse.
+                if !visited.insert(name) {
+                    continue;
+                }
+                if let Some(fragment) = document.fragments.get(&spread.node.fragment_name.node) {
+                    collect_fields(document, &fragment.node.selection_set.node, visited, fields);
+                }
+            }
+        }
+    }
+}
+
+/// Handles automatic persisted queries and the allow-list.
+///
+/// Fills in the query of requests that only send the hash of a persisted query, registers
+/// queries that are sent along with their hash, and rejects queries that aren't allow-listed.
+///
+/// # Returns
+/// * `bool` - Whether the query is known to be on the allow-list. Only looked up when the
+///   allow-list is enforced or the request sent a hash.
+pub async fn resolve_persisted_query(
+    request: &mut async_graphql::Request,
+    config: &config::PersistedQueries,
+    is_admin: bool,
+    db: &PgPool,
+) -> Result<bool, ServerError> {
+    let hash = match request.extensions.get("persistedQuery") {
+        Some(async_graphql::Value::Object(persisted_query)) => {
+            match persisted_query.get("sha256Hash") {
+                Some(async_graphql::Value::String(hash)) => Some(hash.to_lowercase()),
+                _ => return Err(ServerError::new("INVALID_PERSISTED_QUERY", None)),
+            }
+        }
+        Some(_) => return Err(ServerError::new("INVALID_PERSISTED_QUERY", None)),
+        None => None,
+    };
+    if hash.is_some() && !config.automatic && !config.allow_list {
+        return Err(apollo_error(
+            "PersistedQueryNotSupported",
+            "PERSISTED_QUERY_NOT_SUPPORTED",
+        ));
+    }
+
+    let database_error = |err: Error| ServerError::new(err.to_string(), None);
+    let mut persisted_query = None;
+    match hash {
+        Some(hash) if request.query.is_empty() => {
+            let stored = crate::database::persisted_query::get_persisted_query(&hash, db)
+                .await
+                .map_err(database_error)?
+                .ok_or_else(|| {
+                    apollo_error("PersistedQueryNotFound", "PERSISTED_QUERY_NOT_FOUND")
+                })?;
+            request.query = stored.query.clone();
+            persisted_query = Some(stored);
+        }
+        Some(hash) => {
+            if hash_token(&request.query) != hash {
+                return Err(ServerError::new("PERSISTED_QUERY_HASH_MISMATCH", None));
+            }
+            // With the allow-list only admins can add queries.
+            if config.automatic && !config.allow_list {
+                crate::database::persisted_query::register_persisted_query(
+                    &request.query,
+                    false,
+                    db,
+                )
+                .await
+                .map_err(database_error)?;
+            }
+        }
+        None => {}
+    }
+
+    if !config.allow_list || is_admin {
+        return Ok(persisted_query.is_some_and(|persisted_query| persisted_query.allow_listed));
+    }
+
+    let persisted_query = match persisted_query {
+        Some(persisted_query) => Some(persisted_query),
+        None => {
+            crate::database::persisted_query::get_persisted_query(&hash_token(&request.query), db)
+                .await
+                .map_err(database_error)?
+        }
+    };
+    match persisted_query {
+        Some(persisted_query) if persisted_query.allow_listed => Ok(true),
+        _ => Err(ServerError::new("PERSISTED_QUERY_NOT_ALLOWED", None)),
+    }
+}
+
+/// An error in the format Apollo clients look for to fall back to sending the whole query.
+fn apollo_error(message: &str, code: &str) -> ServerError {
+    let mut extensions = ErrorExtensionValues::default();
+    extensions.set("code", code);
+
+    let mut err = ServerError::new(message, None);
+    err.extensions = Some(extensions);
+    err
 }
 
-pub fn make_schema() -> Schema<QueryRoot, MutationRoot, EmptySubscription> {
-    Schema::build(QueryRoot {}, MutationRoot, EmptySubscription).finish()
+fn error_body(err: ServerError) -> String {
+    let response = async_graphql::Response::from_errors(vec![err]);
+    serde_json::to_string(&response).unwrap()
 }
 
 async fn deserialize_body(body: Body) -> Result<async_graphql::Request, io::Error> {
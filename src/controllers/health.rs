@@ -0,0 +1,94 @@
+use std::{io, sync::Arc};
+
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Request, Response, StatusCode,
+};
+use routerify::prelude::*;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+
+use super::graphql::AppSchema;
+use crate::{
+    constants::{APP_VERSION, GIT_COMMIT},
+    utils::{metrics::Metrics, startup, token::hash_token},
+};
+
+/// Liveness probe, answers as long as the process does.
+pub async fn healthz(_: Request<Body>) -> Result<Response<Body>, io::Error> {
+    Ok(json_response(StatusCode::OK, json!({ "status": "ok" })))
+}
+
+/// Readiness probe, fails while the database can't be reached or migrations are pending.
+pub async fn readyz(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = req.data::<PgPool>().unwrap();
+
+    let (ready, database, migrations) = match startup::pending_migrations(db).await {
+        Ok(pending) if pending.is_empty() => (true, "ok".to_string(), "ok".to_string()),
+        Ok(pending) => (
+            false,
+            "ok".to_string(),
+            format!("{} pending", pending.len()),
+        ),
+        Err(err) => (false, err.to_string(), "unknown".to_string()),
+    };
+
+    let (status, text) = if ready {
+        (StatusCode::OK, "ok")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    Ok(json_response(
+        status,
+        json!({
+            "status": text,
+            "checks": {
+                "database": database,
+                "migrations": migrations,
+            },
+        }),
+    ))
+}
+
+/// What's running: the crate version, the commit it was built from and a hash of the
+/// GraphQL schema, which changes whenever the schema does.
+pub async fn version(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let schema = req.data::<Arc<AppSchema>>().unwrap();
+
+    Ok(json_response(
+        StatusCode::OK,
+        json!({
+            "version": APP_VERSION,
+            "commit": GIT_COMMIT,
+            "schema": hash_token(&schema.sdl()),
+        }),
+    ))
+}
+
+/// Metrics in the Prometheus text format.
+pub async fn metrics(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let metrics = req.data::<Arc<Metrics>>().unwrap();
+    let db = req.data::<PgPool>().unwrap();
+
+    Ok(Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )
+        .body(Body::from(metrics.render(db)))
+        .unwrap())
+}
+
+fn json_response(status: StatusCode, value: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )
+        // Probes have to see the current state.
+        .header(header::CACHE_CONTROL, HeaderValue::from_static("no-store"))
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
@@ -1,16 +1,27 @@
 pub mod graphql;
+pub mod health;
+pub mod openapi;
 pub mod page;
+pub mod rest;
+pub mod subscription;
 
 use hyper::{Body, Method};
 use routerify::Router;
 use std::io;
 
 use self::graphql::{graphiql, graphql};
+use crate::utils::middleware::preflight;
 
 pub fn handle_routes() -> Router<Body, io::Error> {
     Router::builder()
         .add("/graphql", vec![Method::GET, Method::POST], graphql)
         .get("/graphiql", graphiql)
+        .get("/healthz", health::healthz)
+        .get("/readyz", health::readyz)
+        .get("/version", health::version)
+        .get("/metrics", health::metrics)
+        .scope("/api/v1", rest::routes())
+        .options("/*", preflight)
         .build()
         .unwrap()
 }
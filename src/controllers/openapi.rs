@@ -0,0 +1,162 @@
+use std::io;
+
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Request, Response,
+};
+use utoipa::{
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use super::rest;
+use crate::{
+    models::{
+        artist::{Artist, ArtistType},
+        release::{Release, ReleaseType},
+        song::{NewSong, Song},
+        tag::Tag,
+        ExternalSite, ExternalSiteType, Name, NewName,
+    },
+    utils::error::ErrorResponse,
+};
+
+/// OpenAPI document of the REST API, generated from the handlers and the types they return.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Weeb Music Database",
+        description = "REST API for songs, artists, releases and tags.",
+        license(name = "GPL-3.0")
+    ),
+    servers((url = "/api/v1")),
+    paths(
+        rest::get_songs,
+        rest::create_song,
+        rest::get_song,
+        rest::get_song_artists,
+        rest::get_song_releases,
+        rest::get_song_tags,
+        rest::get_artists,
+        rest::get_artist,
+        rest::get_releases,
+        rest::get_tags,
+    ),
+    components(schemas(
+        Song,
+        NewSong,
+        Artist,
+        ArtistType,
+        Release,
+        ReleaseType,
+        Tag,
+        Name,
+        NewName,
+        ExternalSite,
+        ExternalSiteType,
+        ErrorResponse,
+    )),
+    modifiers(&BearerAuth)
+)]
+pub struct ApiDoc;
+
+/// Sessions and API keys are both sent as `Authorization: Bearer ...`.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "bearer",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+pub async fn openapi(_: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let json = ApiDoc::openapi().to_pretty_json().unwrap();
+
+    Ok(Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )
+        .body(Body::from(json))
+        .unwrap())
+}
+
+/// Swagger UI for the OpenAPI document, like `/graphiql` is for the GraphQL API.
+pub async fn docs(_: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let html = docs_source("/api/v1/openapi.json");
+
+    Ok(Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        )
+        .body(Body::from(html))
+        .unwrap())
+}
+
+fn docs_source(spec_url: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+  <head>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+    <title>Weeb Music Database REST API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@4/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@4/swagger-ui-bundle.js" crossorigin></script>
+    <script>
+      window.onload = () => {{
+        window.ui = SwaggerUIBundle({{ url: "{spec_url}", dom_id: "#swagger-ui" }});
+      }};
+    </script>
+  </body>
+</html>"##
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(object) => {
+                for (key, value) in object {
+                    match value {
+                        serde_json::Value::String(reference) if key == "$ref" => {
+                            refs.push(reference.clone())
+                        }
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                values.iter().for_each(|value| collect_refs(value, refs))
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_schemas_are_registered() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let schemas = &document["components"]["schemas"];
+
+        let mut refs = Vec::new();
+        collect_refs(&document, &mut refs);
+        assert!(!refs.is_empty());
+        for reference in refs {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            assert!(schemas.get(name).is_some(), "{name} isn't registered");
+        }
+        assert!(document["paths"]["/songs/{id}"]["get"].is_object());
+    }
+}
@@ -0,0 +1,555 @@
+use std::{collections::HashMap, io, str::FromStr, sync::Arc};
+
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Request, Response, StatusCode,
+};
+use routerify::{prelude::*, Router};
+use serde::Serialize;
+use sqlx::PgPool;
+use ulid::Ulid;
+
+use super::openapi;
+use crate::{
+    constants::REST_MAX_PER_PAGE,
+    models::{
+        api_key::{ApiKey, ApiKeyScope},
+        song::NewSong,
+        Name,
+    },
+    utils::{
+        error::{Error, ErrorResponse},
+        middleware::{rate_limit_key, Claims},
+        rate_limit::{Budget, RateLimiter},
+        replicas::Replicas,
+        response_cache::ResponseCache,
+    },
+};
+
+/// Version 1 of the REST API, served under `/api/v1`.
+///
+/// Mirrors the song, artist, release and tag queries of the GraphQL API for clients
+/// that only want plain JSON.
+pub fn routes() -> Router<Body, io::Error> {
+    Router::builder()
+        .get("/songs", get_songs)
+        .post("/songs", create_song)
+        .get("/songs/:id", get_song)
+        .get("/songs/:id/artists", get_song_artists)
+        .get("/songs/:id/releases", get_song_releases)
+        .get("/songs/:id/tags", get_song_tags)
+        .get("/artists", get_artists)
+        .get("/artists/:id", get_artist)
+        .get("/releases", get_releases)
+        .get("/tags", get_tags)
+        .get("/openapi.json", openapi::openapi)
+        .get("/docs", openapi::docs)
+        .build()
+        .unwrap()
+}
+
+/// Songs, optionally filtered.
+#[utoipa::path(
+    get,
+    path = "/songs",
+    tag = "songs",
+    params(
+        ("search" = Option<String>, Query, description = "Part of the song's name, in any language"),
+        ("artist_id" = Option<String>, Query, description = "Only songs by this artist"),
+        ("release_id" = Option<String>, Query, description = "Only songs on this release"),
+        ("genres" = Option<String>, Query, description = "Comma separated genres"),
+        ("page" = Option<i32>, Query, description = "Page to return, starting at 0"),
+        ("per_page" = Option<i32>, Query, description = "Items per page, at most 100"),
+    ),
+    responses(
+        (status = 200, body = [Song]),
+        (status = 400, description = "A parameter is invalid", body = ErrorResponse),
+    )
+)]
+pub async fn get_songs(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let params = Params::from_request(&req);
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::song::Options {
+                id: None,
+                search: params.get("search"),
+                artist_id: params.get("artist_id"),
+                release_id: params.get("release_id"),
+                genres: params.list("genres"),
+                page: params.parse_page("page")?,
+                per_page: params
+                    .parse_page("per_page")?
+                    .map(|per_page| per_page.min(REST_MAX_PER_PAGE)),
+            };
+
+            crate::database::song::get_songs(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// A song by its id.
+#[utoipa::path(
+    get,
+    path = "/songs/{id}",
+    tag = "songs",
+    params(("id" = String, Path, description = "ULID of the song")),
+    responses(
+        (status = 200, body = Song),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub async fn get_song(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let id = req.param("id").unwrap().to_string();
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::song::Options {
+                id: Some(id),
+                search: None,
+                artist_id: None,
+                release_id: None,
+                genres: None,
+                page: None,
+                per_page: None,
+            };
+
+            crate::database::song::get_song(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// Adds a song. Needs a logged in user or an API key that can write.
+#[utoipa::path(
+    post,
+    path = "/songs",
+    tag = "songs",
+    request_body = NewSong,
+    security(("bearer" = [])),
+    responses(
+        (status = 201, body = Song),
+        (status = 400, description = "The body is invalid", body = ErrorResponse),
+        (status = 401, body = ErrorResponse),
+        (status = 403, description = "The API key is read-only", body = ErrorResponse),
+    )
+)]
+pub async fn create_song(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = req.data::<PgPool>().unwrap().clone();
+    let cache = req.data::<Arc<ResponseCache>>().unwrap().clone();
+    let replicas = req.data::<Arc<Replicas>>().unwrap().clone();
+    let key = rate_limit_key(&req);
+    let claims = req.context::<Claims>();
+    let api_key = req.context::<ApiKey>();
+
+    // Writes share the budget of GraphQL mutations, so they can't be used to get around it.
+    let limiter = req.data::<Arc<RateLimiter>>().unwrap();
+    if let Err(limited) = limiter.check(Budget::Mutation, &key) {
+        return Err(io::Error::other(limited));
+    }
+
+    respond(
+        StatusCode::CREATED,
+        async {
+            if claims.is_none() {
+                return Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED));
+            }
+            if api_key.is_some_and(|api_key| api_key.scope == ApiKeyScope::ReadOnly) {
+                return Err(Error::new("API_KEY_READ_ONLY", StatusCode::FORBIDDEN));
+            }
+
+            let bytes = hyper::body::to_bytes(req.into_body())
+                .await
+                .map_err(|_| Error::new("INVALID_BODY", StatusCode::BAD_REQUEST))?;
+            let input: NewSong = serde_json::from_slice(&bytes).map_err(|err| {
+                Error::new(format!("INVALID_BODY: {err}"), StatusCode::BAD_REQUEST)
+            })?;
+            let name = Name {
+                native: input.name.native,
+                romanized: input.name.romanized,
+                english: input.name.english,
+            };
+
+            let song = crate::database::song::create_song(
+                Ulid::new(),
+                name,
+                input.artists,
+                Some(input.releases),
+                &db,
+            )
+            .await?;
+            cache.clear();
+            replicas.record_write(&key);
+
+            Ok(song)
+        }
+        .await,
+    )
+}
+
+/// Artists of a song.
+#[utoipa::path(
+    get,
+    path = "/songs/{id}/artists",
+    tag = "songs",
+    params(("id" = String, Path, description = "ULID of the song")),
+    responses(
+        (status = 200, body = [Artist]),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub async fn get_song_artists(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let id = req.param("id").unwrap();
+
+    respond(
+        StatusCode::OK,
+        async {
+            let id = find_song(id, db).await?;
+            crate::database::artist::get_artists_by_song_id(&id, db).await
+        }
+        .await,
+    )
+}
+
+/// Releases a song is on.
+#[utoipa::path(
+    get,
+    path = "/songs/{id}/releases",
+    tag = "songs",
+    params(("id" = String, Path, description = "ULID of the song")),
+    responses(
+        (status = 200, body = [Release]),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub async fn get_song_releases(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let id = req.param("id").unwrap();
+
+    respond(
+        StatusCode::OK,
+        async {
+            let id = find_song(id, db).await?;
+            crate::database::release::get_releases_by_song_id(&id, db).await
+        }
+        .await,
+    )
+}
+
+/// Tags of a song.
+#[utoipa::path(
+    get,
+    path = "/songs/{id}/tags",
+    tag = "songs",
+    params(("id" = String, Path, description = "ULID of the song")),
+    responses(
+        (status = 200, body = [Tag]),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub async fn get_song_tags(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let id = req.param("id").unwrap();
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::tag::Options::new().song_id(find_song(id, db).await?);
+            crate::database::tag::get_tags(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// Artists, optionally filtered.
+#[utoipa::path(
+    get,
+    path = "/artists",
+    tag = "artists",
+    params(
+        ("search" = Option<String>, Query, description = "Part of the artist's name, in any language"),
+        ("song_id" = Option<String>, Query, description = "Only artists of this song"),
+        ("release_id" = Option<String>, Query, description = "Only artists of this release"),
+        ("page" = Option<i32>, Query, description = "Page to return, starting at 0"),
+        ("per_page" = Option<i32>, Query, description = "Items per page, at most 100"),
+    ),
+    responses(
+        (status = 200, body = [Artist]),
+        (status = 400, description = "A parameter is invalid", body = ErrorResponse),
+    )
+)]
+pub async fn get_artists(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let params = Params::from_request(&req);
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::artist::Options {
+                id: None,
+                search: params.get("search"),
+                song_id: params.get("song_id"),
+                release_id: params.get("release_id"),
+                page: params.parse_page("page")?,
+                per_page: params
+                    .parse_page("per_page")?
+                    .map(|per_page| per_page.min(REST_MAX_PER_PAGE)),
+            };
+
+            crate::database::artist::get_artists(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// An artist by its id.
+#[utoipa::path(
+    get,
+    path = "/artists/{id}",
+    tag = "artists",
+    params(("id" = String, Path, description = "ULID of the artist")),
+    responses(
+        (status = 200, body = Artist),
+        (status = 404, body = ErrorResponse),
+    )
+)]
+pub async fn get_artist(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let id = req.param("id").unwrap().to_string();
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::artist::Options {
+                id: Some(parse_ulid(&id)?.to_string()),
+                search: None,
+                song_id: None,
+                release_id: None,
+                page: None,
+                per_page: None,
+            };
+
+            crate::database::artist::get_artist(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// All releases.
+#[utoipa::path(
+    get,
+    path = "/releases",
+    tag = "releases",
+    params(
+        ("page" = Option<i32>, Query, description = "Page to return, starting at 0"),
+        ("per_page" = Option<i32>, Query, description = "Items per page, at most 100"),
+    ),
+    responses(
+        (status = 200, body = [Release]),
+        (status = 400, description = "A parameter is invalid", body = ErrorResponse),
+    )
+)]
+pub async fn get_releases(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let params = Params::from_request(&req);
+
+    respond(
+        StatusCode::OK,
+        async {
+            let options = crate::models::release::Options {
+                id: None,
+                search: None,
+                artist_id: None,
+                song_id: None,
+                genres: None,
+                page: params.parse_page("page")?,
+                per_page: params
+                    .parse_page("per_page")?
+                    .map(|per_page| per_page.min(REST_MAX_PER_PAGE)),
+            };
+
+            crate::database::release::get_releases(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// Tags, optionally only the one with the given name.
+#[utoipa::path(
+    get,
+    path = "/tags",
+    tag = "tags",
+    params(("name" = Option<String>, Query, description = "Exact name of the tag")),
+    responses((status = 200, body = [Tag]))
+)]
+pub async fn get_tags(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let db = &read_pool(&req);
+    let params = Params::from_request(&req);
+
+    respond(
+        StatusCode::OK,
+        async {
+            let mut options = crate::models::tag::Options::new();
+            if let Some(name) = params.get("name") {
+                options = options.name(name);
+            }
+
+            crate::database::tag::get_tags(&options, db).await
+        }
+        .await,
+    )
+}
+
+/// A read replica for the client, the primary if there's none or the client just wrote.
+fn read_pool(req: &Request<Body>) -> PgPool {
+    let replicas = req.data::<Arc<Replicas>>().unwrap();
+    replicas
+        .read_pool(&rate_limit_key(req))
+        .unwrap_or_else(|| req.data::<PgPool>().unwrap().clone())
+}
+
+/// Responds with the value as JSON, or with the error as an [`ErrorResponse`].
+fn respond<T: Serialize>(
+    status: StatusCode,
+    result: Result<T, Error>,
+) -> Result<Response<Body>, io::Error> {
+    let (status, json) = match result {
+        Ok(value) => (status, serde_json::to_string(&value)),
+        Err(err) => (
+            err.status_code,
+            serde_json::to_string(&ErrorResponse::from(err)),
+        ),
+    };
+
+    Ok(Response::builder()
+        .status(status)
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        )
+        .body(Body::from(json.unwrap()))
+        .unwrap())
+}
+
+/// Ids that aren't ULIDs can't belong to anything.
+fn parse_ulid(id: &str) -> Result<Ulid, Error> {
+    Ulid::from_string(id).map_err(|_| {
+        Error::new(
+            "Could not find the requested resource!",
+            StatusCode::NOT_FOUND,
+        )
+    })
+}
+
+/// Lists of a song's relations are empty for songs that don't exist, check that it does first.
+async fn find_song(id: &str, db: &PgPool) -> Result<Ulid, Error> {
+    let id = parse_ulid(id)?;
+    let options = crate::models::song::Options {
+        id: Some(id.to_string()),
+        search: None,
+        artist_id: None,
+        release_id: None,
+        genres: None,
+        page: None,
+        per_page: None,
+    };
+    crate::database::song::get_song(&options, db).await?;
+
+    Ok(id)
+}
+
+/// Query string parameters of a request.
+struct Params(HashMap<String, String>);
+
+impl Params {
+    fn from_request(req: &Request<Body>) -> Self {
+        let query = req.uri().query().unwrap_or_default();
+        Self(
+            url::form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+        )
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        self.0.get(name).cloned()
+    }
+
+    /// A comma separated list.
+    fn list(&self, name: &str) -> Option<Vec<String>> {
+        self.0
+            .get(name)
+            .map(|value| value.split(',').map(str::to_string).collect())
+    }
+
+    /// # Errors
+    /// * `INVALID_PARAMETER` - If the parameter is there but can't be parsed.
+    fn parse<T: FromStr>(&self, name: &str) -> Result<Option<T>, Error> {
+        self.0
+            .get(name)
+            .map(|value| value.parse())
+            .transpose()
+            .map_err(|_| invalid_parameter(name))
+    }
+
+    /// A page number or size, which can't be negative.
+    ///
+    /// # Errors
+    /// * `INVALID_PARAMETER` - If the parameter is there but isn't a number from 0 up.
+    fn parse_page(&self, name: &str) -> Result<Option<i32>, Error> {
+        match self.parse::<i32>(name)? {
+            Some(value) if value < 0 => Err(invalid_parameter(name)),
+            value => Ok(value),
+        }
+    }
+}
+
+fn invalid_parameter(name: &str) -> Error {
+    Error::new(
+        format!("INVALID_PARAMETER: {name}"),
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::config;
+    use hyper::service::Service;
+    use routerify::RequestServiceBuilder;
+    use sqlx::postgres::PgPoolOptions;
+
+    #[tokio::test]
+    async fn test_unknown_ids() {
+        // Ids that aren't ULIDs are turned away before the database is asked.
+        let pool = PgPoolOptions::new()
+            .connect_lazy("postgres://localhost/unused")
+            .unwrap();
+        let router = Router::builder()
+            .data(pool)
+            .data(Replicas::connect(&config::Db::default()).unwrap())
+            .scope("/api/v1", routes())
+            .build()
+            .unwrap();
+        let mut service = RequestServiceBuilder::new(router)
+            .unwrap()
+            .build("127.0.0.1:1".parse().unwrap());
+
+        for path in [
+            "/api/v1/artists/unknown",
+            "/api/v1/songs/unknown/artists",
+            "/api/v1/songs/unknown/releases",
+            "/api/v1/songs/unknown/tags",
+        ] {
+            let req = Request::get(path).body(Body::empty()).unwrap();
+            let res = service.call(req).await.unwrap();
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{path}");
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::{future::ready, io, str::FromStr, sync::Arc};
+
+use async_graphql::{
+    async_trait,
+    futures_util::stream::BoxStream,
+    http::{WebSocket, WebSocketProtocols, WsMessage},
+    parser::types::OperationType,
+    Context, Data, Executor, ServerError, Subscription,
+};
+use futures::{stream, SinkExt, Stream, StreamExt};
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Request, Response, StatusCode,
+};
+use hyper_tungstenite::tungstenite::{
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    Message,
+};
+use routerify::prelude::*;
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use super::graphql::{resolve_persisted_query, selected_operation, AppSchema, SelectedOperation};
+use crate::{
+    models::{
+        api_key::ApiKey,
+        entity_change::{ChangeOperation, EntityChange, EntityKind},
+        song::Song,
+        user::AccessLevel,
+    },
+    utils::{
+        config::Config,
+        entity_events::EntityEvents,
+        middleware::{authenticate, rate_limit_key, Claims, ClientInfo},
+        rate_limit::{Budget, RateLimiter},
+    },
+};
+
+pub struct SubscriptionRoot;
+
+#[Subscription]
+impl SubscriptionRoot {
+    /// Songs as they're added.
+    async fn song_added(&self, context: &Context<'_>) -> impl Stream<Item = Song> {
+        let db = context.data_unchecked::<PgPool>().clone();
+        let events = context.data_unchecked::<Arc<EntityEvents>>();
+
+        events.subscribe().filter_map(move |change| {
+            let db = db.clone();
+            async move {
+                if change.kind != EntityKind::Song || change.operation != ChangeOperation::Insert {
+                    return None;
+                }
+
+                let options = crate::models::song::Options {
+                    id: Some(change.id),
+                    search: None,
+                    artist_id: None,
+                    release_id: None,
+                    genres: None,
+                    page: None,
+                    per_page: None,
+                };
+                crate::database::song::get_song(&options, &db)
+                    .await
+                    .map_err(|err| error!("Failed to get added song: {err}"))
+                    .ok()
+            }
+        })
+    }
+
+    /// Changes to the song, artist or release with the given id.
+    async fn entity_updated(
+        &self,
+        context: &Context<'_>,
+        id: String,
+    ) -> impl Stream<Item = EntityChange> {
+        let events = context.data_unchecked::<Arc<EntityEvents>>();
+
+        events.subscribe().filter(move |change| {
+            ready(change.operation == ChangeOperation::Update && change.id == id)
+        })
+    }
+}
+
+/// Serves GraphQL subscriptions over a WebSocket, speaking either `graphql-transport-ws`
+/// or the older `graphql-ws` protocol.
+///
+/// Browsers can't set headers on WebSockets, so clients can also authenticate by sending
+/// `{ "Authorization": "Bearer ..." }` as the payload of `connection_init`.
+pub async fn graphql_ws(mut req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let protocol = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        })
+        .unwrap_or(WebSocketProtocols::GraphQLWS);
+
+    let (mut response, websocket) = match hyper_tungstenite::upgrade(&mut req, None) {
+        Ok(upgrade) => upgrade,
+        Err(err) => {
+            info!("Invalid WebSocket upgrade: {err}");
+            let mut response = Response::new(Body::from("Invalid WebSocket upgrade"));
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            return Ok(response);
+        }
+    };
+    response.headers_mut().insert(
+        header::SEC_WEBSOCKET_PROTOCOL,
+        HeaderValue::from_static(protocol.sec_websocket_protocol()),
+    );
+
+    let schema = req.data::<Arc<AppSchema>>().unwrap().clone();
+    let db = req.data::<PgPool>().unwrap().clone();
+    let config = req.data::<Config>().unwrap().clone();
+    let limiter = req.data::<Arc<RateLimiter>>().unwrap().clone();
+    let events = req.data::<Arc<EntityEvents>>().unwrap().clone();
+
+    let mut data = Data::default();
+    data.insert(db.clone());
+    data.insert(config.clone());
+    data.insert(events);
+    data.insert(ClientInfo::from_request(&req));
+    if let Some(claims) = req.context::<Claims>() {
+        data.insert(claims);
+    }
+    if let Some(api_key) = req.context::<ApiKey>() {
+        data.insert(api_key);
+    }
+
+    let executor = SubscriptionExecutor {
+        schema,
+        db: db.clone(),
+        config: config.clone(),
+        limiter,
+        key: rate_limit_key(&req),
+    };
+
+    tokio::spawn(async move {
+        let websocket = match websocket.await {
+            Ok(websocket) => websocket,
+            Err(err) => {
+                error!("Failed to upgrade to a WebSocket: {err}");
+                return;
+            }
+        };
+        let (mut sink, stream) = websocket.split();
+
+        let input = stream
+            .take_while(|message| ready(message.is_ok()))
+            .filter_map(|message| {
+                ready(match message {
+                    Ok(Message::Text(text)) => Some(text.into_bytes()),
+                    Ok(Message::Binary(bytes)) => Some(bytes),
+                    _ => None,
+                })
+            });
+
+        let mut output = WebSocket::new(executor, input, protocol)
+            .connection_data(data)
+            .on_connection_init(move |payload| connection_init(payload, config, db));
+
+        while let Some(message) = output.next().await {
+            let message = match message {
+                WsMessage::Text(text) => Message::Text(text),
+                WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                })),
+            };
+
+            if sink.send(message).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok(response)
+}
+
+/// Authenticates the connection with the token in the `connection_init` payload, if any.
+async fn connection_init(
+    payload: serde_json::Value,
+    config: Config,
+    db: PgPool,
+) -> async_graphql::Result<Data> {
+    let mut data = Data::default();
+    let token = ["Authorization", "authorization", "token"]
+        .iter()
+        .find_map(|key| payload.get(key).and_then(|value| value.as_str()));
+
+    if let Some(token) = token {
+        match authenticate(token.trim_start_matches("Bearer "), &config, &db).await {
+            Ok(Some((claims, api_key))) => {
+                data.insert(claims);
+                if let Some(api_key) = api_key {
+                    data.insert(api_key);
+                }
+            }
+            Ok(None) => return Err("INVALID_TOKEN".into()),
+            Err(err) => return Err(err.to_string().into()),
+        }
+    }
+
+    Ok(data)
+}
+
+/// Only runs subscriptions, queries and mutations have to go through `POST /graphql`
+/// where rate limits and API key scopes are enforced.
+#[derive(Clone)]
+struct SubscriptionExecutor {
+    schema: Arc<AppSchema>,
+    db: PgPool,
+    config: Config,
+    limiter: Arc<RateLimiter>,
+    key: String,
+}
+
+impl SubscriptionExecutor {
+    async fn prepare(
+        &self,
+        request: &mut async_graphql::Request,
+        session_data: Option<&Data>,
+    ) -> Result<(), ServerError> {
+        let is_admin = session_data
+            .and_then(|data| data.get(&std::any::TypeId::of::<Claims>()))
+            .and_then(|claims| claims.downcast_ref::<Claims>())
+            .is_some_and(|claims| claims.access_level == AccessLevel::Admin);
+        resolve_persisted_query(request, &self.config.persisted_queries, is_admin, &self.db)
+            .await?;
+
+        if !matches!(
+            selected_operation(request),
+            Some(SelectedOperation {
+                ty: OperationType::Subscription,
+                ..
+            })
+        ) {
+            return Err(ServerError::new("ONLY_SUBSCRIPTIONS_ALLOWED", None));
+        }
+
+        self.limiter
+            .check(Budget::Query, &self.key)
+            .map_err(|_| ServerError::new("RATE_LIMITED", None))
+    }
+}
+
+#[async_trait::async_trait]
+impl Executor for SubscriptionExecutor {
+    async fn execute(&self, _: async_graphql::Request) -> async_graphql::Response {
+        async_graphql::Response::from_errors(vec![ServerError::new(
+            "ONLY_SUBSCRIPTIONS_ALLOWED",
+            None,
+        )])
+    }
+
+    fn execute_stream(
+        &self,
+        mut request: async_graphql::Request,
+        session_data: Option<Arc<Data>>,
+    ) -> BoxStream<'static, async_graphql::Response> {
+        let executor = self.clone();
+
+        stream::once(async move {
+            if let Err(err) = executor
+                .prepare(&mut request, session_data.as_deref())
+                .await
+            {
+                let response = async_graphql::Response::from_errors(vec![err]);
+                return stream::once(ready(response)).boxed();
+            }
+
+            executor
+                .schema
+                .execute_stream_with_session_data(request, session_data.unwrap_or_default())
+                .boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+}
@@ -0,0 +1,224 @@
+use sea_query::{Alias, Cond, Expr, Func, Order, PostgresQueryBuilder, Query, Values};
+use sqlx::{types::chrono::Utc, Executor, PgPool, Postgres};
+use tracing::{debug, Instrument};
+
+use crate::{
+    constants::AUTH_API_KEY_PREFIX,
+    database::query_span,
+    models::api_key::{ApiKey, ApiKeyIden, NewApiKey, Options},
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::{
+        error::Error,
+        token::{generate_token, hash_token},
+    },
+};
+
+/// How many characters of the key are stored in the clear to identify it.
+const PREFIX_LENGTH: usize = AUTH_API_KEY_PREFIX.len() + 8;
+
+/// Creates a new API key for the user with the given id.
+///
+/// # Returns
+/// * `(ApiKey, String)` - The stored key and the key itself, which can't be retrieved later.
+pub async fn create_api_key(
+    user_id: &str,
+    input: NewApiKey,
+    db: &PgPool,
+) -> Result<(ApiKey, String), Error> {
+    if input.name.trim().is_empty() {
+        return Err(Error::new("INVALID_NAME", hyper::StatusCode::BAD_REQUEST));
+    }
+
+    if matches!(input.expires_at, Some(expires_at) if expires_at <= Utc::now()) {
+        return Err(Error::new("INVALID_EXPIRY", hyper::StatusCode::BAD_REQUEST));
+    }
+
+    let key = format!("{AUTH_API_KEY_PREFIX}{}", generate_token());
+    let (query, values) = Query::insert()
+        .into_table(ApiKeyIden::Table)
+        .columns(vec![
+            ApiKeyIden::Id,
+            ApiKeyIden::UserId,
+            ApiKeyIden::Name,
+            ApiKeyIden::Prefix,
+            ApiKeyIden::KeyHash,
+            ApiKeyIden::Scope,
+            ApiKeyIden::ExpiresAt,
+        ])
+        .exprs(vec![
+            Expr::val(ulid::Ulid::new().to_string()).into(),
+            Expr::val(user_id.to_string()).into(),
+            Expr::val(input.name.trim().to_string()).into(),
+            Expr::val(key[..PREFIX_LENGTH].to_string()).into(),
+            Expr::val(hash_token(&key)).into(),
+            Func::cast_as(input.scope, Alias::new("api_key_scope")),
+            Expr::val(input.expires_at).into(),
+        ])
+        .unwrap()
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let api_key: ApiKey = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_one(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok((api_key, key))
+}
+
+/// Returns the API key with the given id.
+///
+/// # Errors
+/// * `API_KEY_NOT_FOUND` - If the key doesn't exist.
+pub async fn get_api_key(id: &str, db: &PgPool) -> Result<ApiKey, Error> {
+    let options = Options {
+        id: Some(id.to_string()),
+        user_id: None,
+        active: false,
+    };
+    let (query, values) = build_query(&options);
+
+    let api_key: Option<ApiKey> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    api_key.ok_or_else(|| Error::new("API_KEY_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
+}
+
+/// Returns API keys matching the options, newest first.
+pub async fn get_api_keys(options: &Options, db: &PgPool) -> Result<Vec<ApiKey>, Error> {
+    let (query, values) = build_query(options);
+
+    debug!("{}", query);
+
+    let api_keys: Vec<ApiKey> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_all(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(api_keys)
+}
+
+/// Looks up an active API key by the key itself and marks it as used.
+///
+/// # Returns
+/// * `None` - If the key doesn't exist, was revoked or has expired.
+pub async fn authenticate_api_key(key: &str, db: &PgPool) -> Result<Option<ApiKey>, Error> {
+    let (query, values) = Query::update()
+        .table(ApiKeyIden::Table)
+        .value(ApiKeyIden::LastUsedAt, Utc::now().into())
+        .and_where(Expr::col(ApiKeyIden::KeyHash).eq(hash_token(key)))
+        .cond_where(active_condition())
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    let api_key: Option<ApiKey> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(api_key)
+}
+
+/// Revokes the API key with the given id.
+pub async fn revoke_api_key(id: &str, db: &PgPool) -> Result<ApiKey, Error> {
+    let (query, values) = Query::update()
+        .table(ApiKeyIden::Table)
+        .value(ApiKeyIden::RevokedAt, Utc::now().into())
+        .and_where(Expr::col(ApiKeyIden::Id).eq(id.to_string()))
+        .and_where(Expr::col(ApiKeyIden::RevokedAt).is_null())
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let api_key: Option<ApiKey> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    match api_key {
+        Some(api_key) => Ok(api_key),
+        // Revoking twice is a no-op, but make sure the key exists at all.
+        None => get_api_key(id, db).await,
+    }
+}
+
+/// Revokes every API key of the user with the given id.
+pub async fn revoke_user_api_keys<'c>(
+    user_id: &str,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<(), Error> {
+    let (query, values) = Query::update()
+        .table(ApiKeyIden::Table)
+        .value(ApiKeyIden::RevokedAt, Utc::now().into())
+        .and_where(Expr::col(ApiKeyIden::UserId).eq(user_id.to_string()))
+        .and_where(Expr::col(ApiKeyIden::RevokedAt).is_null())
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+fn active_condition() -> Cond {
+    Cond::all()
+        .add(Expr::col(ApiKeyIden::RevokedAt).is_null())
+        .add(
+            Cond::any()
+                .add(Expr::col(ApiKeyIden::ExpiresAt).is_null())
+                .add(Expr::col(ApiKeyIden::ExpiresAt).gt(Utc::now())),
+        )
+}
+
+fn build_query(options: &Options) -> (String, Values) {
+    let mut q = Query::select();
+    q.expr(Expr::table_asterisk(ApiKeyIden::Table));
+    q.from(ApiKeyIden::Table);
+
+    if let Some(id) = &options.id {
+        q.and_where(Expr::col(ApiKeyIden::Id).eq(id.clone()));
+    }
+
+    if let Some(user_id) = &options.user_id {
+        q.and_where(Expr::col(ApiKeyIden::UserId).eq(user_id.clone()));
+    }
+
+    if options.active {
+        q.cond_where(active_condition());
+    }
+
+    q.order_by(ApiKeyIden::CreatedAt, Order::Desc);
+
+    q.build(PostgresQueryBuilder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_active() {
+        let options = Options {
+            id: None,
+            user_id: Some("00000000000000000000000000".to_string()),
+            active: true,
+        };
+        let (query, values) = build_query(&options);
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT api_keys.* FROM api_keys WHERE user_id = $1 AND (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > $2)) ORDER BY created_at DESC"
+        );
+        assert_eq!(values.0.len(), 2);
+    }
+}
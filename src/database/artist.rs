@@ -1,4 +1,5 @@
 use crate::{
+    database::query_span,
     models::{
         artist::{Artist, ArtistIden, Options, SongArtistIden},
         release::SongReleaseIden,
@@ -8,7 +9,7 @@ use crate::{
 
 use sea_query::{Expr, JoinType, PostgresQueryBuilder, Query, Values};
 use sqlx::PgPool;
-use tracing::debug;
+use tracing::{debug, Instrument};
 use ulid::Ulid;
 
 use crate::sea_query_driver_postgres::bind_query_as;
@@ -38,8 +39,8 @@ pub async fn get_artists_by_song_id(id: &Ulid, db: &PgPool) -> Result<Vec<Artist
 
     let artists: Vec<Artist> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
-        .await
-        .unwrap();
+        .instrument(query_span(&query))
+        .await?;
 
     Ok(artists)
 }
@@ -48,18 +49,20 @@ pub async fn get_artists_by_song_id(id: &Ulid, db: &PgPool) -> Result<Vec<Artist
 /// # Arguments
 /// * `options` - options for artist
 /// * `db` - database connection
+/// # Errors
+/// * `ARTIST_NOT_FOUND` - If no artist matches the options.
 pub async fn get_artist(options: &Options, db: &PgPool) -> Result<Artist, Error> {
     let (query, values) = build_query(options);
 
     debug!("{}", query);
 
     // execute the query
-    let artist: Artist = bind_query_as(sqlx::query_as(&query), &values)
-        .fetch_one(db)
-        .await
-        .unwrap();
+    let artist: Option<Artist> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
 
-    Ok(artist)
+    artist.ok_or_else(|| Error::new("ARTIST_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
 }
 
 /// get artists with options
@@ -76,8 +79,8 @@ pub async fn get_artists(options: &Options, db: &PgPool) -> Result<Vec<Artist>,
     // execute the query
     let artists: Vec<Artist> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
-        .await
-        .unwrap();
+        .instrument(query_span(&query))
+        .await?;
 
     Ok(artists)
 }
@@ -123,13 +126,24 @@ fn build_query(options: &Options) -> (String, Values) {
     }
 
     if let Some(release_id) = &options.release_id {
-        q.join(
-            JoinType::LeftJoin,
-            ArtistIden::Table,
-            Expr::tbl(SongReleaseIden::Table, SongReleaseIden::SongId)
-                .equals(ArtistIden::Table, ArtistIden::Id),
-        )
-        .and_where(Expr::col(SongReleaseIden::ReleaseId).eq(release_id.clone()));
+        // Artists of any song on the release, each only once.
+        q.and_where(
+            Expr::col((ArtistIden::Table, ArtistIden::Id)).in_subquery(
+                Query::select()
+                    .column((SongArtistIden::Table, SongArtistIden::ArtistId))
+                    .from(SongArtistIden::Table)
+                    .inner_join(
+                        SongReleaseIden::Table,
+                        Expr::tbl(SongReleaseIden::Table, SongReleaseIden::SongId)
+                            .equals(SongArtistIden::Table, SongArtistIden::SongId),
+                    )
+                    .and_where(
+                        Expr::col((SongReleaseIden::Table, SongReleaseIden::ReleaseId))
+                            .eq(release_id.clone()),
+                    )
+                    .take(),
+            ),
+        );
     }
 
     if options.page.is_some() || options.per_page.is_some() {
@@ -139,3 +153,26 @@ fn build_query(options: &Options) -> (String, Values) {
 
     q.build(PostgresQueryBuilder)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_with_release_id() {
+        let options = Options {
+            id: None,
+            search: None,
+            song_id: None,
+            release_id: Some("00000000000000000000000000".to_string()),
+            page: None,
+            per_page: None,
+        };
+        let (query, values) = build_query(&options);
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT artists.id, artists.name, artists.alt_names, artists.external_sites, artists.description, artists.based_in, artists.founded_in, artists.artist_type FROM artists WHERE artists.id IN (SELECT songs_artists.artist_id FROM songs_artists INNER JOIN songs_releases ON songs_releases.song_id = songs_artists.song_id WHERE songs_releases.release_id = $1)"
+        );
+        assert_eq!(values.0, vec!["00000000000000000000000000".into()]);
+    }
+}
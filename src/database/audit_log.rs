@@ -0,0 +1,254 @@
+use sea_query::{Alias, BinOper, Cond, Expr, Func, Order, PostgresQueryBuilder, Query, Values};
+use sqlx::{Executor, PgPool, Postgres};
+use tracing::{debug, Instrument};
+
+use crate::{
+    constants::AUDIT_LOG_MAX_PER_PAGE,
+    database::query_span,
+    models::{
+        audit_log::{AuditLog, AuditLogIden, NewAuditLog, Options},
+        user::UserIden,
+    },
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::error::Error,
+};
+
+/// Appends an entry to the audit log.
+///
+/// # Arguments
+/// * `entry` - The entry to be recorded.
+/// * `db` - database connection
+/// # Returns
+/// * `AuditLog` - The stored entry.
+pub async fn create_audit_log(entry: NewAuditLog, db: &PgPool) -> Result<AuditLog, Error> {
+    let (query, values) = Query::insert()
+        .into_table(AuditLogIden::Table)
+        .columns(vec![
+            AuditLogIden::ActorId,
+            AuditLogIden::Action,
+            AuditLogIden::Target,
+            AuditLogIden::IpAddress,
+            AuditLogIden::Details,
+        ])
+        .exprs(vec![
+            Expr::val(entry.actor_id).into(),
+            Func::cast_as(entry.action, Alias::new("audit_action")),
+            Expr::val(entry.target).into(),
+            Expr::val(entry.ip_address).into(),
+            Expr::val(entry.details).into(),
+        ])
+        .unwrap()
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let audit_log: AuditLog = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_one(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(audit_log)
+}
+
+/// Returns audit log entries matching the options, newest first.
+///
+/// # Arguments
+/// * `options` - filters and pagination for the entries
+/// * `db` - database connection
+/// # Returns
+/// * `Vec<AuditLog>` - matching entries
+/// # Errors
+/// * `INVALID_PARAMETER` - If `page` is negative or `per_page` is below 1.
+pub async fn get_audit_logs(options: &Options, db: &PgPool) -> Result<Vec<AuditLog>, Error> {
+    check_pagination(options)?;
+    let (query, values) = build_query(options);
+
+    debug!("{}", query);
+
+    let audit_logs: Vec<AuditLog> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_all(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(audit_logs)
+}
+
+/// Clears the target of entries that name the user with the given id by email or username,
+/// like failed logins. Has to run before the user's own row is anonymized.
+pub async fn anonymize_user_targets<'c>(
+    user_id: &str,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<(), Error> {
+    let (query, values) = build_anonymize_query(user_id);
+
+    debug!("Query: {}", query);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+fn build_anonymize_query(user_id: &str) -> (String, Values) {
+    // Logins are attempted with whatever case the client typed.
+    let names = |column: UserIden| {
+        Query::select()
+            .expr(Func::lower(Expr::col(column)))
+            .from(UserIden::Table)
+            .and_where(Expr::col(UserIden::Id).eq(user_id.to_string()))
+            .to_owned()
+    };
+
+    Query::update()
+        .table(AuditLogIden::Table)
+        .value(AuditLogIden::Target, Option::<String>::None.into())
+        .cond_where(
+            Cond::any()
+                .add(
+                    Expr::expr(Func::lower(Expr::col(AuditLogIden::Target)))
+                        .in_subquery(names(UserIden::Email)),
+                )
+                .add(
+                    Expr::expr(Func::lower(Expr::col(AuditLogIden::Target)))
+                        .in_subquery(names(UserIden::Username)),
+                ),
+        )
+        .to_owned()
+        .build(PostgresQueryBuilder)
+}
+
+fn build_query(options: &Options) -> (String, Values) {
+    let mut q = Query::select();
+    q.expr(Expr::table_asterisk(AuditLogIden::Table));
+    q.from(AuditLogIden::Table);
+
+    if let Some(actor_id) = &options.actor_id {
+        q.and_where(Expr::col(AuditLogIden::ActorId).eq(actor_id.clone()));
+    }
+
+    if let Some(action) = options.action {
+        q.and_where(Expr::col(AuditLogIden::Action).binary(
+            BinOper::Equal,
+            Func::cast_as(action, Alias::new("audit_action")),
+        ));
+    }
+
+    if let Some(target) = &options.target {
+        q.and_where(Expr::col(AuditLogIden::Target).eq(target.clone()));
+    }
+
+    if let Some(since) = options.since {
+        q.and_where(Expr::col(AuditLogIden::CreatedAt).gte(since));
+    }
+
+    if let Some(until) = options.until {
+        q.and_where(Expr::col(AuditLogIden::CreatedAt).lt(until));
+    }
+
+    q.order_by(AuditLogIden::CreatedAt, Order::Desc);
+
+    if options.page.is_some() || options.per_page.is_some() {
+        let per_page = options.per_page.unwrap_or(50).min(AUDIT_LOG_MAX_PER_PAGE) as u64;
+        q.limit(per_page);
+        q.offset(options.page.unwrap_or(0) as u64 * per_page);
+    }
+
+    q.build(PostgresQueryBuilder)
+}
+
+fn check_pagination(options: &Options) -> Result<(), Error> {
+    let invalid = |name: &str| {
+        Error::new(
+            format!("INVALID_PARAMETER: {name}"),
+            hyper::StatusCode::BAD_REQUEST,
+        )
+    };
+
+    if options.page.is_some_and(|page| page < 0) {
+        return Err(invalid("page"));
+    }
+    if options.per_page.is_some_and(|per_page| per_page < 1) {
+        return Err(invalid("per_page"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::audit_log::AuditAction;
+
+    #[test]
+    fn test_build_query() {
+        let (query, values) = build_query(&Options::default());
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT audit_logs.* FROM audit_logs ORDER BY created_at DESC"
+        );
+        assert!(values.0.is_empty());
+    }
+
+    #[test]
+    fn test_build_query_with_filters() {
+        let options = Options {
+            actor_id: Some("00000000000000000000000000".to_string()),
+            action: Some(AuditAction::Login),
+            page: Some(2),
+            per_page: Some(10),
+            ..Options::default()
+        };
+        let (query, values) = build_query(&options);
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT audit_logs.* FROM audit_logs WHERE actor_id = $1 AND action = CAST($2 AS audit_action) ORDER BY created_at DESC LIMIT $3 OFFSET $4"
+        );
+        assert_eq!(
+            values.0,
+            vec![
+                "00000000000000000000000000".into(),
+                "Login".into(),
+                10u64.into(),
+                20u64.into()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pagination() {
+        let options = |page, per_page| Options {
+            page,
+            per_page,
+            ..Options::default()
+        };
+        assert!(check_pagination(&options(Some(0), Some(1))).is_ok());
+        assert!(check_pagination(&options(Some(-1), None)).is_err());
+        assert!(check_pagination(&options(None, Some(0))).is_err());
+        assert!(check_pagination(&options(None, Some(-1))).is_err());
+
+        let (query, values) = build_query(&options(Some(1), Some(10_000)));
+        assert!(query.ends_with("LIMIT $1 OFFSET $2"));
+        assert_eq!(values.0, vec![100u64.into(), 100u64.into()]);
+    }
+
+    #[test]
+    fn test_build_anonymize_query() {
+        let (query, values) = build_anonymize_query("00000000000000000000000000");
+        assert_eq!(
+            query.replace('\"', ""),
+            "UPDATE audit_logs SET target = $1 WHERE LOWER(target) IN (SELECT LOWER(email) FROM users WHERE id = $2) OR LOWER(target) IN (SELECT LOWER(username) FROM users WHERE id = $3)"
+        );
+        assert_eq!(
+            values.0,
+            vec![
+                Option::<String>::None.into(),
+                "00000000000000000000000000".into(),
+                "00000000000000000000000000".into()
+            ]
+        );
+    }
+}
@@ -0,0 +1,81 @@
+use serde_json::{Map, Value};
+use sqlx::{types::Json, PgPool, Row};
+use tracing::{debug, Instrument};
+
+use crate::{
+    constants::{CATALOG_EXPORT_VERSION, CATALOG_TABLES},
+    database::query_span,
+    utils::error::Error,
+};
+
+/// Returns the rows of every catalog table as JSON, keyed by table name.
+///
+/// Users and everything tied to them are left out, the export is meant to be shared.
+pub async fn export_catalog(db: &PgPool) -> Result<Value, Error> {
+    let mut tables = Map::new();
+
+    for table in CATALOG_TABLES {
+        let query = format!("SELECT coalesce(jsonb_agg(t), '[]'::jsonb) FROM {table} t");
+        debug!("Query: {}", query);
+
+        let rows: Json<Value> = sqlx::query(&query)
+            .fetch_one(db)
+            .instrument(query_span(&query))
+            .await?
+            .try_get(0)?;
+        tables.insert(table.to_string(), rows.0);
+    }
+
+    Ok(serde_json::json!({
+        "version": CATALOG_EXPORT_VERSION,
+        "tables": tables,
+    }))
+}
+
+/// Inserts the rows of an export, skipping the ones that already exist.
+///
+/// # Returns
+/// * `Vec<(&str, u64)>` - How many rows were added to each table.
+/// # Errors
+/// * `INVALID_EXPORT` - If it isn't an export of this version.
+pub async fn import_catalog(
+    export: &Value,
+    db: &PgPool,
+) -> Result<Vec<(&'static str, u64)>, Error> {
+    if export["version"] != CATALOG_EXPORT_VERSION {
+        return Err(Error::new("INVALID_EXPORT", hyper::StatusCode::BAD_REQUEST));
+    }
+
+    let mut added = Vec::new();
+    let mut tx = db.begin().await?;
+
+    // The tables are in an order that inserts rows before anything referencing them.
+    for table in CATALOG_TABLES {
+        let rows = export["tables"]
+            .get(table)
+            .cloned()
+            .unwrap_or(Value::Array(Vec::new()));
+        let query = format!(
+            "INSERT INTO {table} SELECT * FROM jsonb_populate_recordset(NULL::{table}, $1) ON CONFLICT DO NOTHING"
+        );
+        debug!("Query: {}", query);
+
+        let result = sqlx::query(&query)
+            .bind(Json(rows))
+            .execute(&mut tx)
+            .instrument(query_span(&query))
+            .await?;
+        added.push((table, result.rows_affected()));
+    }
+
+    // Tags have serial ids, which have to continue after the imported ones.
+    let query = "SELECT setval(pg_get_serial_sequence('tags', 'id'), coalesce(max(id), 0) + 1, false) FROM tags";
+    sqlx::query(query)
+        .execute(&mut tx)
+        .instrument(query_span(query))
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(added)
+}
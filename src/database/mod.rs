@@ -1,5 +1,23 @@
+pub mod api_key;
 pub mod artist;
+pub mod audit_log;
+pub mod catalog;
+pub mod oidc;
+pub mod persisted_query;
 pub mod release;
+pub mod session;
 pub mod song;
 pub mod tag;
+pub mod two_factor;
 pub mod user;
+pub mod user_token;
+
+/// Span of a database query, exported with its statement as a child of the resolver running it.
+pub fn query_span(statement: &str) -> tracing::Span {
+    tracing::info_span!(
+        "query",
+        otel.kind = "client",
+        db.system = "postgresql",
+        db.statement = statement
+    )
+}
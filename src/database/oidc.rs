@@ -0,0 +1,165 @@
+use sea_query::{Expr, Func, OnConflict, PostgresQueryBuilder, Query};
+use sqlx::{types::chrono::Utc, Executor, PgPool, Postgres};
+use tracing::{debug, Instrument};
+
+use crate::{
+    constants::OIDC_STATE_EXPIRATION,
+    database::query_span,
+    models::oidc::{OidcState, OidcStateIden, UserIdentity, UserIdentityIden},
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::{error::Error, oidc::Authorization},
+};
+
+/// Remembers a login that was just started at the provider with the given name.
+pub async fn create_oidc_state(
+    provider: &str,
+    authorization: &Authorization,
+    db: &PgPool,
+) -> Result<(), Error> {
+    // Clean up logins that were never finished while at it.
+    let (query, values) = Query::delete()
+        .from_table(OidcStateIden::Table)
+        .and_where(Expr::col(OidcStateIden::ExpiresAt).lte(Utc::now()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    let (query, values) = Query::insert()
+        .into_table(OidcStateIden::Table)
+        .columns(vec![
+            OidcStateIden::State,
+            OidcStateIden::Provider,
+            OidcStateIden::Nonce,
+            OidcStateIden::CodeVerifier,
+            OidcStateIden::ExpiresAt,
+        ])
+        .values_panic(vec![
+            authorization.state.clone().into(),
+            provider.to_string().into(),
+            authorization.nonce.clone().into(),
+            authorization.code_verifier.clone().into(),
+            (Utc::now() + chrono::Duration::seconds(OIDC_STATE_EXPIRATION)).into(),
+        ])
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+/// Removes the login with the given state and returns it, so every state can only be used once.
+///
+/// # Errors
+/// * `INVALID_STATE` - If the state doesn't exist, was already used or has expired.
+pub async fn consume_oidc_state(state: &str, db: &PgPool) -> Result<OidcState, Error> {
+    let (query, values) = Query::delete()
+        .from_table(OidcStateIden::Table)
+        .and_where(Expr::col(OidcStateIden::State).eq(state.to_string()))
+        .and_where(Expr::col(OidcStateIden::ExpiresAt).gt(Utc::now()))
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let oidc_state: Option<OidcState> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    oidc_state.ok_or_else(|| Error::new("INVALID_STATE", hyper::StatusCode::BAD_REQUEST))
+}
+
+/// Returns the identity with the given subject at the provider, if it's linked to a user.
+pub async fn get_user_identity(
+    provider: &str,
+    subject: &str,
+    db: &PgPool,
+) -> Result<Option<UserIdentity>, Error> {
+    let (query, values) = Query::select()
+        .expr(Expr::asterisk())
+        .from(UserIdentityIden::Table)
+        .and_where(Expr::col(UserIdentityIden::Provider).eq(provider.to_string()))
+        .and_where(Expr::col(UserIdentityIden::Subject).eq(subject.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let identity: Option<UserIdentity> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(identity)
+}
+
+/// Links the identity to the user with the given id, or records a new login if it already is.
+pub async fn upsert_user_identity(
+    provider: &str,
+    subject: &str,
+    user_id: &str,
+    email: Option<String>,
+    db: &PgPool,
+) -> Result<(), Error> {
+    let (query, values) = Query::insert()
+        .into_table(UserIdentityIden::Table)
+        .columns(vec![
+            UserIdentityIden::Provider,
+            UserIdentityIden::Subject,
+            UserIdentityIden::UserId,
+            UserIdentityIden::Email,
+        ])
+        .values_panic(vec![
+            provider.to_string().into(),
+            subject.to_string().into(),
+            user_id.to_string().into(),
+            email.into(),
+        ])
+        .on_conflict(
+            OnConflict::columns(vec![UserIdentityIden::Provider, UserIdentityIden::Subject])
+                .update_column(UserIdentityIden::Email)
+                .update_expr((UserIdentityIden::LastLoginAt, Func::current_timestamp()))
+                .to_owned(),
+        )
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+/// Unlinks every identity of the user with the given id.
+pub async fn delete_user_identities<'c>(
+    user_id: &str,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<(), Error> {
+    let (query, values) = Query::delete()
+        .from_table(UserIdentityIden::Table)
+        .and_where(Expr::col(UserIdentityIden::UserId).eq(user_id.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use sea_query::{Expr, OnConflict, Order, PostgresQueryBuilder, Query};
+use sqlx::PgPool;
+use tracing::{debug, Instrument};
+
+use crate::{
+    database::query_span,
+    models::persisted_query::{PersistedQuery, PersistedQueryIden},
+    sea_query_driver_postgres::bind_query_as,
+    utils::{error::Error, token::hash_token},
+};
+
+/// Returns the persisted query with the given hash, if there is one.
+pub async fn get_persisted_query(hash: &str, db: &PgPool) -> Result<Option<PersistedQuery>, Error> {
+    let (query, values) = Query::select()
+        .expr(Expr::asterisk())
+        .from(PersistedQueryIden::Table)
+        .and_where(Expr::col(PersistedQueryIden::Hash).eq(hash.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let persisted_query: Option<PersistedQuery> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(persisted_query)
+}
+
+/// Returns the persisted queries, oldest first.
+///
+/// # Arguments
+/// * `allow_listed` - Only return queries that are, or aren't, allow-listed.
+pub async fn get_persisted_queries(
+    allow_listed: Option<bool>,
+    db: &PgPool,
+) -> Result<Vec<PersistedQuery>, Error> {
+    let mut q = Query::select();
+    q.expr(Expr::asterisk())
+        .from(PersistedQueryIden::Table)
+        .order_by(PersistedQueryIden::CreatedAt, Order::Asc);
+
+    if let Some(allow_listed) = allow_listed {
+        q.and_where(Expr::col(PersistedQueryIden::AllowListed).eq(allow_listed));
+    }
+
+    let (query, values) = q.build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let persisted_queries: Vec<PersistedQuery> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_all(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(persisted_queries)
+}
+
+/// Stores the query under its hash. Registering a query again only ever adds it to the allow-list.
+pub async fn register_persisted_query(
+    query: &str,
+    allow_listed: bool,
+    db: &PgPool,
+) -> Result<PersistedQuery, Error> {
+    let (sql, values) = Query::insert()
+        .into_table(PersistedQueryIden::Table)
+        .columns(vec![
+            PersistedQueryIden::Hash,
+            PersistedQueryIden::Query,
+            PersistedQueryIden::AllowListed,
+        ])
+        .values_panic(vec![
+            hash_token(query).into(),
+            query.to_string().into(),
+            allow_listed.into(),
+        ])
+        .on_conflict(
+            OnConflict::column(PersistedQueryIden::Hash)
+                .update_expr((
+                    PersistedQueryIden::AllowListed,
+                    Expr::cust("persisted_queries.allow_listed OR EXCLUDED.allow_listed"),
+                ))
+                .to_owned(),
+        )
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", sql);
+
+    let persisted_query: PersistedQuery = bind_query_as(sqlx::query_as(&sql), &values)
+        .fetch_one(db)
+        .instrument(query_span(&sql))
+        .await?;
+
+    Ok(persisted_query)
+}
+
+/// Removes the persisted query with the given hash.
+///
+/// # Errors
+/// * `PERSISTED_QUERY_NOT_FOUND` - If there is no query with the hash.
+pub async fn delete_persisted_query(hash: &str, db: &PgPool) -> Result<PersistedQuery, Error> {
+    let (query, values) = Query::delete()
+        .from_table(PersistedQueryIden::Table)
+        .and_where(Expr::col(PersistedQueryIden::Hash).eq(hash.to_string()))
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let persisted_query: Option<PersistedQuery> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    persisted_query
+        .ok_or_else(|| Error::new("PERSISTED_QUERY_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
+}
@@ -1,4 +1,5 @@
 use crate::{
+    database::query_span,
     models::{
         release::{Options, Release, ReleaseIden, SongReleaseIden},
         song::SongIden,
@@ -7,19 +8,19 @@ use crate::{
 };
 use sea_query::{Alias, Expr, PostgresQueryBuilder, Query};
 use sqlx::PgPool;
-use tracing::debug;
+use tracing::{debug, Instrument};
 use ulid::Ulid;
 
 use crate::sea_query_driver_postgres::bind_query_as;
 
-pub async fn get_releases(_options: &Options, db: &PgPool) -> Result<Vec<Release>, Error> {
+pub async fn get_releases(options: &Options, db: &PgPool) -> Result<Vec<Release>, Error> {
     let sr: sea_query::DynIden = sea_query::SeaRc::new(sea_query::Alias::new("sr"));
     let s: sea_query::DynIden = sea_query::SeaRc::new(sea_query::Alias::new("s"));
     let total_length: sea_query::DynIden =
         sea_query::SeaRc::new(sea_query::Alias::new("total_length"));
-    let (query, values) = Query::select()
-        // Get all columns from the release table
-        .expr(Expr::table_asterisk(ReleaseIden::Table))
+    let mut q = Query::select();
+    // Get all columns from the release table
+    q.expr(Expr::table_asterisk(ReleaseIden::Table))
         .expr(Expr::col(total_length.clone()))
         .from(ReleaseIden::Table)
         .join_subquery(
@@ -43,11 +44,18 @@ pub async fn get_releases(_options: &Options, db: &PgPool) -> Result<Vec<Release
             s.clone(),
             Expr::col((ReleaseIden::Table, ReleaseIden::Id))
                 .equals(s.clone(), SongReleaseIden::ReleaseId),
-        )
-        .build(PostgresQueryBuilder);
+        );
+
+    if options.page.is_some() || options.per_page.is_some() {
+        q.limit(options.per_page.unwrap_or(50) as u64);
+        q.offset(options.page.unwrap_or(0) as u64 * options.per_page.unwrap_or(50) as u64);
+    }
+
+    let (query, values) = q.build(PostgresQueryBuilder);
 
     let releases: Vec<Release> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(releases)
@@ -87,8 +95,8 @@ pub async fn get_releases_by_song_id(id: &Ulid, db: &PgPool) -> Result<Vec<Relea
 
     let releases: Vec<Release> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
-        .await
-        .unwrap();
+        .instrument(query_span(&query))
+        .await?;
 
     Ok(releases)
 }
@@ -0,0 +1,255 @@
+use sea_query::{Cond, Expr, Order, PostgresQueryBuilder, Query, Values};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Executor, PgPool, Postgres,
+};
+use tracing::{debug, Instrument};
+
+use crate::{
+    database::query_span,
+    models::session::{Options, Session, SessionIden},
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::error::Error,
+};
+
+/// Creates a new session for the user with the given id.
+///
+/// # Arguments
+/// * `ip_address` - Remote address of the client logging in.
+/// * `user_agent` - User agent of the client logging in.
+/// * `expires_at` - When the session expires unless it's refreshed.
+pub async fn create_session(
+    user_id: &str,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    expires_at: DateTime<Utc>,
+    db: &PgPool,
+) -> Result<Session, Error> {
+    let (query, values) = Query::insert()
+        .into_table(SessionIden::Table)
+        .columns(vec![
+            SessionIden::Id,
+            SessionIden::UserId,
+            SessionIden::IpAddress,
+            SessionIden::UserAgent,
+            SessionIden::ExpiresAt,
+        ])
+        .values_panic(vec![
+            ulid::Ulid::new().to_string().into(),
+            user_id.to_string().into(),
+            ip_address.into(),
+            user_agent.into(),
+            expires_at.into(),
+        ])
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let session: Session = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_one(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(session)
+}
+
+/// Returns the session with the given id.
+///
+/// # Errors
+/// * `SESSION_NOT_FOUND` - If the session doesn't exist.
+pub async fn get_session(id: &str, db: &PgPool) -> Result<Session, Error> {
+    let options = Options {
+        id: Some(id.to_string()),
+        user_id: None,
+        active: false,
+    };
+    let (query, values) = build_query(&options);
+
+    let session: Option<Session> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    session.ok_or_else(|| Error::new("SESSION_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
+}
+
+/// Returns sessions matching the options, most recently used first.
+pub async fn get_sessions(options: &Options, db: &PgPool) -> Result<Vec<Session>, Error> {
+    let (query, values) = build_query(options);
+
+    debug!("{}", query);
+
+    let sessions: Vec<Session> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_all(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(sessions)
+}
+
+/// Whether the session with the given id exists and is neither revoked nor expired.
+pub async fn is_session_active(id: &str, db: &PgPool) -> Result<bool, Error> {
+    let options = Options {
+        id: Some(id.to_string()),
+        user_id: None,
+        active: true,
+    };
+    let (query, values) = build_query(&options);
+
+    let session: Option<Session> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(session.is_some())
+}
+
+/// Marks the session as used and pushes its expiry forward.
+pub async fn extend_session(id: &str, expires_at: DateTime<Utc>, db: &PgPool) -> Result<(), Error> {
+    let (query, values) = Query::update()
+        .table(SessionIden::Table)
+        .value(SessionIden::LastUsedAt, Utc::now().into())
+        .value(SessionIden::ExpiresAt, expires_at.into())
+        .and_where(Expr::col(SessionIden::Id).eq(id.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+/// Revokes the session with the given id, invalidating its refresh and access tokens.
+pub async fn revoke_session(id: &str, db: &PgPool) -> Result<Session, Error> {
+    let (query, values) = Query::update()
+        .table(SessionIden::Table)
+        .value(SessionIden::RevokedAt, Utc::now().into())
+        .and_where(Expr::col(SessionIden::Id).eq(id.to_string()))
+        .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let session: Option<Session> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    match session {
+        Some(session) => Ok(session),
+        // Revoking twice is a no-op, but make sure the session exists at all.
+        None => get_session(id, db).await,
+    }
+}
+
+/// Revokes every session of the user with the given id.
+pub async fn revoke_user_sessions<'c>(
+    user_id: &str,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<(), Error> {
+    let (query, values) = Query::update()
+        .table(SessionIden::Table)
+        .value(SessionIden::RevokedAt, Utc::now().into())
+        .and_where(Expr::col(SessionIden::UserId).eq(user_id.to_string()))
+        .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+/// Revokes every session of the user with the given id except the one with the given session id.
+pub async fn revoke_other_sessions(
+    user_id: &str,
+    session_id: &str,
+    db: &PgPool,
+) -> Result<(), Error> {
+    let (query, values) = Query::update()
+        .table(SessionIden::Table)
+        .value(SessionIden::RevokedAt, Utc::now().into())
+        .and_where(Expr::col(SessionIden::UserId).eq(user_id.to_string()))
+        .and_where(Expr::col(SessionIden::Id).ne(session_id.to_string()))
+        .and_where(Expr::col(SessionIden::RevokedAt).is_null())
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+fn build_query(options: &Options) -> (String, Values) {
+    let mut q = Query::select();
+    q.expr(Expr::table_asterisk(SessionIden::Table));
+    q.from(SessionIden::Table);
+
+    if let Some(id) = &options.id {
+        q.and_where(Expr::col(SessionIden::Id).eq(id.clone()));
+    }
+
+    if let Some(user_id) = &options.user_id {
+        q.and_where(Expr::col(SessionIden::UserId).eq(user_id.clone()));
+    }
+
+    if options.active {
+        q.cond_where(
+            Cond::all()
+                .add(Expr::col(SessionIden::RevokedAt).is_null())
+                .add(Expr::col(SessionIden::ExpiresAt).gt(Utc::now())),
+        );
+    }
+
+    q.order_by(SessionIden::LastUsedAt, Order::Desc);
+
+    q.build(PostgresQueryBuilder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_query_with_user_id() {
+        let options = Options {
+            id: None,
+            user_id: Some("00000000000000000000000000".to_string()),
+            active: false,
+        };
+        let (query, values) = build_query(&options);
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT sessions.* FROM sessions WHERE user_id = $1 ORDER BY last_used_at DESC"
+        );
+        assert_eq!(values.0, vec!["00000000000000000000000000".into()]);
+    }
+
+    #[test]
+    fn test_build_query_active() {
+        let options = Options {
+            id: Some("00000000000000000000000000".to_string()),
+            user_id: None,
+            active: true,
+        };
+        let (query, values) = build_query(&options);
+        assert_eq!(
+            query.replace('\"', ""),
+            "SELECT sessions.* FROM sessions WHERE id = $1 AND (revoked_at IS NULL AND expires_at > $2) ORDER BY last_used_at DESC"
+        );
+        assert_eq!(values.0.len(), 2);
+    }
+}
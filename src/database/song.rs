@@ -1,4 +1,5 @@
 use crate::{
+    database::query_span,
     models::{
         artist::SongArtistIden,
         release::SongReleaseIden,
@@ -9,7 +10,7 @@ use crate::{
 };
 use sea_query::{Expr, JoinType, PostgresQueryBuilder, Query, Values};
 use sqlx::PgPool;
-use tracing::debug;
+use tracing::{debug, Instrument};
 
 use crate::sea_query_driver_postgres::bind_query_as;
 
@@ -28,6 +29,7 @@ pub async fn get_song(options: &Options, db: &PgPool) -> Result<Song, Error> {
 
     let song: Song = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_one(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(song)
@@ -48,6 +50,7 @@ pub async fn get_songs(options: &Options, db: &PgPool) -> Result<Vec<Song>, Erro
 
     let songs: Vec<Song> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(songs)
@@ -64,7 +67,7 @@ pub async fn create_song(
         .into_table(SongIden::Table)
         .columns([SongIden::Id, SongIden::Name])
         .values_panic(vec![ulid.to_string().into(), name.clone().into()])
-        .returning(Query::returning().columns([SongIden::Id, SongIden::Name]))
+        .returning_all()
         .build(PostgresQueryBuilder);
 
     debug!("Query: {}", query);
@@ -74,6 +77,7 @@ pub async fn create_song(
         .bind(ulid.to_string())
         .bind(name)
         .fetch_one(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(song)
@@ -1,7 +1,9 @@
 use sea_query::{Expr, JoinType, PostgresQueryBuilder, Query, Values};
 use sqlx::PgPool;
+use tracing::Instrument;
 
 use crate::{
+    database::query_span,
     models::tag::{Options, ReleaseTagIden, SongTagIden, Tag, TagIden},
     sea_query_driver_postgres::bind_query_as,
     utils::error::Error,
@@ -12,6 +14,7 @@ pub async fn get_tags(options: &Options, db: &PgPool) -> Result<Vec<Tag>, Error>
 
     let tag: Vec<Tag> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_all(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(tag)
@@ -0,0 +1,264 @@
+use sea_query::{Cond, Expr, PostgresQueryBuilder, Query};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Executor, PgPool, Postgres,
+};
+use tracing::{debug, Instrument};
+
+use crate::{
+    constants::AUTH_RECOVERY_CODE_COUNT,
+    database::{query_span, user::update_user},
+    models::{
+        two_factor::RecoveryCodeIden,
+        user::{User, UserIden},
+    },
+    sea_query_driver_postgres::bind_query,
+    utils::{
+        error::Error,
+        token::{generate_token, hash_token},
+        totp,
+    },
+};
+
+/// How a user proved their second factor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondFactor {
+    Totp,
+    RecoveryCode,
+}
+
+/// Stores a new TOTP secret for the user. Two-factor authentication is only enabled
+/// once a code generated from it is confirmed.
+///
+/// # Errors
+/// * `TWO_FACTOR_ALREADY_ENABLED` - If the user already uses two-factor authentication.
+pub async fn start_two_factor(user: &User, db: &PgPool) -> Result<String, Error> {
+    if user.has_two_factor() {
+        return Err(Error::new(
+            "TWO_FACTOR_ALREADY_ENABLED",
+            hyper::StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::TotpSecret, secret.clone().into())
+        .value(
+            UserIden::TotpEnabledAt,
+            Option::<DateTime<Utc>>::None.into(),
+        )
+        .value(UserIden::TotpLastStep, Option::<i64>::None.into());
+
+    update_user(&user.id.to_string(), q, db).await?;
+
+    Ok(secret)
+}
+
+/// Enables two-factor authentication once the user proved their authenticator app works.
+///
+/// # Returns
+/// * `Vec<String>` - Fresh recovery codes, they can't be retrieved again.
+/// # Errors
+/// * `TWO_FACTOR_NOT_STARTED` - If `start_two_factor` wasn't called first.
+/// * `INVALID_TWO_FACTOR_CODE` - If the code doesn't match the secret.
+pub async fn confirm_two_factor(
+    user: &User,
+    code: &str,
+    db: &PgPool,
+) -> Result<Vec<String>, Error> {
+    if user.has_two_factor() {
+        return Err(Error::new(
+            "TWO_FACTOR_ALREADY_ENABLED",
+            hyper::StatusCode::BAD_REQUEST,
+        ));
+    }
+
+    let secret = user
+        .totp_secret
+        .as_deref()
+        .ok_or_else(|| Error::new("TWO_FACTOR_NOT_STARTED", hyper::StatusCode::BAD_REQUEST))?;
+    let step = totp::verify(secret, code, None)?.ok_or_else(invalid_code)?;
+
+    let user_id = user.id.to_string();
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::TotpEnabledAt, Utc::now().into())
+        .value(UserIden::TotpLastStep, step.into());
+
+    update_user(&user_id, q, db).await?;
+
+    regenerate_recovery_codes(&user_id, db).await
+}
+
+/// Checks a code from the user's authenticator app, or one of their recovery codes.
+/// Either can only be used once.
+///
+/// # Errors
+/// * `TWO_FACTOR_NOT_ENABLED` - If the user doesn't use two-factor authentication.
+/// * `INVALID_TWO_FACTOR_CODE` - If the code is wrong or was already used.
+pub async fn verify_second_factor(
+    user: &User,
+    code: &str,
+    db: &PgPool,
+) -> Result<SecondFactor, Error> {
+    let secret = match (&user.totp_secret, user.has_two_factor()) {
+        (Some(secret), true) => secret,
+        _ => {
+            return Err(Error::new(
+                "TWO_FACTOR_NOT_ENABLED",
+                hyper::StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+
+    let user_id = user.id.to_string();
+    if let Some(step) = totp::verify(secret, code, user.totp_last_step)? {
+        // Only one request can move the step forward, so a code can't be replayed concurrently.
+        let (query, values) = Query::update()
+            .table(UserIden::Table)
+            .value(UserIden::TotpLastStep, step.into())
+            .and_where(Expr::col(UserIden::Id).eq(user_id))
+            .cond_where(
+                Cond::any()
+                    .add(Expr::col(UserIden::TotpLastStep).is_null())
+                    .add(Expr::col(UserIden::TotpLastStep).lt(step)),
+            )
+            .to_owned()
+            .build(PostgresQueryBuilder);
+
+        let result = bind_query(sqlx::query(&query), &values)
+            .execute(db)
+            .instrument(query_span(&query))
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(invalid_code());
+        }
+
+        return Ok(SecondFactor::Totp);
+    }
+
+    let (query, values) = Query::update()
+        .table(RecoveryCodeIden::Table)
+        .value(RecoveryCodeIden::UsedAt, Utc::now().into())
+        .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id))
+        .and_where(
+            Expr::col(RecoveryCodeIden::CodeHash).eq(hash_token(&normalize_recovery_code(code))),
+        )
+        .and_where(Expr::col(RecoveryCodeIden::UsedAt).is_null())
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    let result = bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+    if result.rows_affected() == 0 {
+        return Err(invalid_code());
+    }
+
+    Ok(SecondFactor::RecoveryCode)
+}
+
+/// Replaces the recovery codes of the user with the given id.
+///
+/// # Returns
+/// * `Vec<String>` - The new recovery codes, they can't be retrieved again.
+pub async fn regenerate_recovery_codes(user_id: &str, db: &PgPool) -> Result<Vec<String>, Error> {
+    let codes: Vec<String> = (0..AUTH_RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+
+    let mut tx = db.begin().await?;
+
+    delete_recovery_codes(user_id, &mut tx).await?;
+
+    let mut q = Query::insert();
+    q.into_table(RecoveryCodeIden::Table)
+        .columns(vec![RecoveryCodeIden::UserId, RecoveryCodeIden::CodeHash]);
+    for code in &codes {
+        q.values_panic(vec![
+            user_id.to_string().into(),
+            hash_token(&normalize_recovery_code(code)).into(),
+        ]);
+    }
+    let (query, values) = q.build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(&mut tx)
+        .instrument(query_span(&query))
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(codes)
+}
+
+/// Turns off two-factor authentication for the user with the given id and drops their recovery codes.
+pub async fn disable_two_factor(user_id: &str, db: &PgPool) -> Result<User, Error> {
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::TotpSecret, Option::<String>::None.into())
+        .value(
+            UserIden::TotpEnabledAt,
+            Option::<DateTime<Utc>>::None.into(),
+        )
+        .value(UserIden::TotpLastStep, Option::<i64>::None.into());
+
+    let user = update_user(user_id, q, db).await?;
+    delete_recovery_codes(user_id, db).await?;
+
+    Ok(user)
+}
+
+/// Deletes every recovery code of the user with the given id.
+pub async fn delete_recovery_codes<'c>(
+    user_id: &str,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<(), Error> {
+    let (query, values) = Query::delete()
+        .from_table(RecoveryCodeIden::Table)
+        .and_where(Expr::col(RecoveryCodeIden::UserId).eq(user_id.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(())
+}
+
+/// Recovery codes look like `1a2b3-c4d5e`, the dash is only there to make them easier to read.
+fn generate_recovery_code() -> String {
+    let token = generate_token();
+    format!("{}-{}", &token[..5], &token[5..10])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn invalid_code() -> Error {
+    Error::new("INVALID_TWO_FACTOR_CODE", hyper::StatusCode::UNAUTHORIZED)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovery_code() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(&code[5..6], "-");
+        assert_eq!(normalize_recovery_code(&code).len(), 10);
+        assert_eq!(normalize_recovery_code(" 1A2B3-C4D5E "), "1a2b3c4d5e");
+    }
+}
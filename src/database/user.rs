@@ -1,29 +1,48 @@
-use sea_query::{Alias, Expr, Func, PostgresQueryBuilder, Query};
-use sqlx::PgPool;
-use tracing::debug;
-use ulid::Ulid;
+use sea_query::{Alias, Expr, Func, PostgresQueryBuilder, Query, UpdateStatement};
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Executor, PgPool, Postgres,
+};
+use tracing::{debug, Instrument};
 
 use crate::{
-    constants::{APP_NAME, AUTH_DEFAULT_REFRESH_TOKEN_EXPIRATION, JWT_DEFAULT_EXPIRATION},
+    constants::{
+        APP_NAME, AUTH_DEFAULT_ACCESS_LEVEL, AUTH_DEFAULT_REFRESH_TOKEN_EXPIRATION,
+        AUTH_EMAIL_VERIFICATION_EXPIRATION, AUTH_PASSWORD_RESET_EXPIRATION,
+        AUTH_TWO_FACTOR_CHALLENGE_EXPIRATION, JWT_DEFAULT_EXPIRATION,
+    },
+    database::{query_span, two_factor::SecondFactor},
     models::{
         refresh_token::{RefreshToken, RefreshTokenIden, RefreshedToken},
         user::{AccessLevel, User, UserIden},
+        user_token::UserTokenKind,
     },
-    sea_query_driver_postgres::bind_query_as,
-    utils::{config::get_config, error::Error},
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::{config::Config, error::Error, oidc::IdTokenClaims, token::generate_token},
 };
 
 #[derive(Debug, async_graphql::SimpleObject)]
 pub struct LoginResponse {
-    pub token: String,
-    pub refresh_token: String,
+    /// Empty until the user passed `verifyTwoFactor`, if they use two-factor authentication.
+    pub token: Option<String>,
+    pub refresh_token: Option<String>,
+    /// Pass to `verifyTwoFactor` along with a code to finish logging in.
+    pub two_factor_token: Option<String>,
     pub user: User,
 }
 
+/// Verifies the credentials and starts a new session for the user.
+///
+/// # Arguments
+/// * `ip_address` - Remote address of the client, stored with the session.
+/// * `user_agent` - User agent of the client, stored with the session.
 pub async fn login(
     email: Option<String>,
     username: Option<String>,
     password: String,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    conf: &Config,
     db: &PgPool,
 ) -> Result<LoginResponse, Error> {
     let mut q = Query::select();
@@ -40,26 +59,279 @@ pub async fn login(
 
     let user: User = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_one(db)
+        .instrument(query_span(&query))
         .await?;
 
     match bcrypt::verify(&password, &user.password_hash) {
-        Ok(result) => result,
-        Err(_) => return Err(Error::new("UNAUTHORIZED", hyper::StatusCode::UNAUTHORIZED)),
+        Ok(true) => {}
+        _ => return Err(Error::new("UNAUTHORIZED", hyper::StatusCode::UNAUTHORIZED)),
     };
 
-    let token = create_token(user.clone())?;
-    let refresh_token = create_refresh_token(user.id, db).await?;
+    complete_login(user, ip_address, user_agent, conf, db).await
+}
 
-    Ok(LoginResponse {
+/// How a login through an OpenID Connect provider found its user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OidcLink {
+    /// The identity was already linked to the user.
+    Existing,
+    /// The identity was linked to the user with the same verified email address.
+    Linked,
+    /// A new user was created for the identity.
+    Created,
+}
+
+/// Logs in the user the verified identity at the provider belongs to, linking it to the user
+/// with the same verified email address or creating a new user if there is none.
+///
+/// # Arguments
+/// * `provider` - Name of the provider from the config.
+/// * `claims` - Claims of the verified ID token.
+///
+/// # Errors
+/// * `OIDC_EMAIL_REQUIRED` - If a user has to be created but the provider didn't share an email.
+/// * `USER_ALREADY_EXISTS` - If the email is taken but it isn't verified on both sides.
+pub async fn oidc_login(
+    provider: &str,
+    claims: &IdTokenClaims,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    conf: &Config,
+    db: &PgPool,
+) -> Result<(LoginResponse, OidcLink), Error> {
+    let identity = crate::database::oidc::get_user_identity(provider, &claims.sub, db).await?;
+
+    let (user, link) = match identity {
+        Some(identity) => {
+            let options = crate::models::user::Options {
+                id: Some(identity.user_id),
+                email: None,
+                page: None,
+                per_page: None,
+            };
+            (get_user(&options, db).await?, OidcLink::Existing)
+        }
+        None => {
+            let email = claims
+                .email
+                .clone()
+                .ok_or_else(|| Error::new("OIDC_EMAIL_REQUIRED", hyper::StatusCode::BAD_REQUEST))?;
+            let options = crate::models::user::Options {
+                id: None,
+                email: Some(email.clone()),
+                page: None,
+                per_page: None,
+            };
+
+            match get_user(&options, db).await {
+                // Only link when both sides proved they own the address. Anyone could claim it at
+                // the provider, and an unverified local account may have been registered by
+                // someone else who would keep their password.
+                Ok(user) if claims.email_verified && user.email_verified_at.is_some() => {
+                    (user, OidcLink::Linked)
+                }
+                Ok(_) => {
+                    return Err(Error::new(
+                        "USER_ALREADY_EXISTS",
+                        hyper::StatusCode::BAD_REQUEST,
+                    ))
+                }
+                Err(err) if err.status_code == hyper::StatusCode::NOT_FOUND => (
+                    create_oidc_user(email, claims, db).await?,
+                    OidcLink::Created,
+                ),
+                Err(err) => return Err(err),
+            }
+        }
+    };
+
+    crate::database::oidc::upsert_user_identity(
+        provider,
+        &claims.sub,
+        &user.id.to_string(),
+        claims.email.clone(),
+        db,
+    )
+    .await?;
+
+    let response = complete_login(user, ip_address, user_agent, conf, db).await?;
+
+    Ok((response, link))
+}
+
+/// Creates a user for an identity at a provider. The user gets a random password,
+/// they can set one with a password reset.
+async fn create_oidc_user(
+    email: String,
+    claims: &IdTokenClaims,
+    db: &PgPool,
+) -> Result<User, Error> {
+    let base = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or_else(|| email.split('@').next().unwrap_or_default());
+    let username = available_username(base, db).await?;
+
+    let user = create_user(
+        email,
+        username,
+        generate_token(),
+        AUTH_DEFAULT_ACCESS_LEVEL,
+        db,
+    )
+    .await?;
+
+    if !claims.email_verified {
+        return Ok(user);
+    }
+
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::EmailVerifiedAt, Utc::now().into());
+
+    update_user(&user.id.to_string(), q, db).await
+}
+
+/// Turns the username suggested by a provider into one that is valid and not taken yet.
+async fn available_username(base: &str, db: &PgPool) -> Result<String, Error> {
+    let base: String = base
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        .collect();
+    let base = if base.is_empty() {
+        "user".to_string()
+    } else {
+        base
+    };
+
+    let mut username = base.clone();
+    loop {
+        let (query, values) = Query::select()
+            .expr(Expr::col(UserIden::Id))
+            .from(UserIden::Table)
+            .and_where(Expr::col(UserIden::Username).eq(username.clone()))
+            .to_owned()
+            .build(PostgresQueryBuilder);
+
+        let taken = bind_query(sqlx::query(&query), &values)
+            .fetch_optional(db)
+            .instrument(query_span(&query))
+            .await?
+            .is_some();
+        if !taken {
+            return Ok(username);
+        }
+
+        username = format!("{base}-{}", &generate_token()[..6]);
+    }
+}
+
+/// Finishes a login once the user proved who they are,
+/// asking for their second factor first if they use two-factor authentication.
+async fn complete_login(
+    user: User,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    conf: &Config,
+    db: &PgPool,
+) -> Result<LoginResponse, Error> {
+    if user.is_suspended() {
+        return Err(Error::new("USER_SUSPENDED", hyper::StatusCode::FORBIDDEN));
+    }
+
+    if user.has_two_factor() {
+        let two_factor_token = crate::database::user_token::create_user_token(
+            &user.id.to_string(),
+            UserTokenKind::TwoFactorChallenge,
+            AUTH_TWO_FACTOR_CHALLENGE_EXPIRATION,
+            db,
+        )
+        .await?;
+
+        return Ok(LoginResponse {
+            token: None,
+            refresh_token: None,
+            two_factor_token: Some(two_factor_token),
+            user,
+        });
+    }
+
+    start_session(user, ip_address, user_agent, conf, db).await
+}
+
+/// Returns the user a login waiting for its second factor belongs to.
+///
+/// Every token can only be used once, a wrong code means logging in again.
+pub async fn consume_two_factor_token(token: &str, db: &PgPool) -> Result<User, Error> {
+    let user_token = crate::database::user_token::consume_user_token(
         token,
-        refresh_token,
+        UserTokenKind::TwoFactorChallenge,
+        db,
+    )
+    .await?;
+
+    let options = crate::models::user::Options {
+        id: Some(user_token.user_id),
+        email: None,
+        page: None,
+        per_page: None,
+    };
+
+    get_user(&options, db).await
+}
+
+/// Checks the second factor of a login and starts the session.
+///
+/// # Errors
+/// * `INVALID_TWO_FACTOR_CODE` - If the code is wrong or was already used.
+pub async fn verify_two_factor_login(
+    user: User,
+    code: &str,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    conf: &Config,
+    db: &PgPool,
+) -> Result<(LoginResponse, SecondFactor), Error> {
+    if user.is_suspended() {
+        return Err(Error::new("USER_SUSPENDED", hyper::StatusCode::FORBIDDEN));
+    }
+
+    let second_factor = crate::database::two_factor::verify_second_factor(&user, code, db).await?;
+    let response = start_session(user, ip_address, user_agent, conf, db).await?;
+
+    Ok((response, second_factor))
+}
+
+/// Starts a new session for the user and issues its first access and refresh token.
+async fn start_session(
+    user: User,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    conf: &Config,
+    db: &PgPool,
+) -> Result<LoginResponse, Error> {
+    let user_id = user.id.to_string();
+    let session = crate::database::session::create_session(
+        &user_id,
+        ip_address,
+        user_agent,
+        refresh_token_expiry(),
+        db,
+    )
+    .await?;
+
+    let token = create_token(user.clone(), &session.id, conf)?;
+    let refresh_token = create_refresh_token(&user_id, &session.id, db).await?;
+
+    Ok(LoginResponse {
+        token: Some(token),
+        refresh_token: Some(refresh_token),
+        two_factor_token: None,
         user,
     })
 }
 
-fn create_token(user: User) -> Result<String, Error> {
-    let conf = get_config();
-
+fn create_token(user: User, session_id: &str, conf: &Config) -> Result<String, Error> {
     let login_claim = crate::utils::middleware::Claims {
         iss: APP_NAME.to_string(),
         aud: APP_NAME.to_string(),
@@ -68,9 +340,9 @@ fn create_token(user: User) -> Result<String, Error> {
         exp: (chrono::Utc::now() + chrono::Duration::seconds(JWT_DEFAULT_EXPIRATION as i64))
             .timestamp() as usize,
         ulid: user.id.to_string(),
-        access_level: user.access_level,
+        access_level: user.effective_access_level(&conf.two_factor),
         //Session ID
-        sid: "".to_string(),
+        sid: session_id.to_string(),
     };
 
     let token = jsonwebtoken::encode(
@@ -82,7 +354,11 @@ fn create_token(user: User) -> Result<String, Error> {
     Ok(token)
 }
 
-async fn create_refresh_token(user_id: Ulid, db: &PgPool) -> Result<String, Error> {
+async fn create_refresh_token(
+    user_id: &str,
+    session_id: &str,
+    db: &PgPool,
+) -> Result<String, Error> {
     // Randomly generate a token
     let token = ulid::Ulid::new().to_string();
 
@@ -91,26 +367,31 @@ async fn create_refresh_token(user_id: Ulid, db: &PgPool) -> Result<String, Erro
         .into_table(RefreshTokenIden::Table)
         .columns(vec![
             RefreshTokenIden::UserId,
+            RefreshTokenIden::SessionId,
             RefreshTokenIden::Token,
             RefreshTokenIden::ExpiresAt,
         ])
         .values_panic(vec![
             user_id.to_string().into(),
+            session_id.to_string().into(),
             token.clone().into(),
-            (chrono::Utc::now()
-                + chrono::Duration::minutes(AUTH_DEFAULT_REFRESH_TOKEN_EXPIRATION as i64))
-            .into(),
+            refresh_token_expiry().into(),
         ])
         .to_owned()
         .build(PostgresQueryBuilder);
 
     let _refresh_token: Option<RefreshToken> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_optional(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(token)
 }
 
+fn refresh_token_expiry() -> DateTime<Utc> {
+    Utc::now() + chrono::Duration::seconds(AUTH_DEFAULT_REFRESH_TOKEN_EXPIRATION as i64)
+}
+
 pub async fn create_user(
     email: String,
     username: String,
@@ -118,6 +399,9 @@ pub async fn create_user(
     access_level: AccessLevel,
     db: &PgPool,
 ) -> Result<User, Error> {
+    // Make sure the email at least looks like one
+    email.parse::<lettre::Address>()?;
+
     // Check if the email or username is already taken
     let (query, values) = Query::select()
         .expr(Expr::asterisk())
@@ -131,6 +415,7 @@ pub async fn create_user(
 
     let user: Option<User> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_optional(db)
+        .instrument(query_span(&query))
         .await?;
 
     if user.is_some() {
@@ -170,6 +455,7 @@ pub async fn create_user(
 
     let user: User = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_one(db)
+        .instrument(query_span(&query))
         .await?;
 
     Ok(user)
@@ -188,6 +474,7 @@ pub async fn get_user(options: &crate::models::user::Options, db: &PgPool) -> Re
 
     let user: Option<User> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_optional(db)
+        .instrument(query_span(&query))
         .await
         .unwrap();
 
@@ -198,52 +485,58 @@ pub async fn get_user(options: &crate::models::user::Options, db: &PgPool) -> Re
     Ok(user.unwrap())
 }
 
-pub async fn refresh_token(refresh_token: String, db: &PgPool) -> Result<RefreshedToken, Error> {
-    let (query, values) = Query::select()
-        .expr(Expr::asterisk())
-        .from(RefreshTokenIden::Table)
+/// Exchanges a refresh token for a new access token and a new refresh token.
+///
+/// Refresh tokens can only be used once. Presenting one that was already rotated means it
+/// leaked, so the whole session gets revoked.
+pub async fn refresh_token(
+    refresh_token: String,
+    conf: &Config,
+    db: &PgPool,
+) -> Result<RefreshedToken, Error> {
+    // Consume the token, only one request can flip it to revoked.
+    let (query, values) = Query::update()
+        .table(RefreshTokenIden::Table)
+        .value(RefreshTokenIden::Revoked, true.into())
+        .value(RefreshTokenIden::UpdatedAt, Utc::now().into())
         .and_where(Expr::col(RefreshTokenIden::Token).eq(refresh_token.clone()))
+        .and_where(Expr::col(RefreshTokenIden::Revoked).eq(false))
+        .returning_all()
         .to_owned()
         .build(PostgresQueryBuilder);
 
-    let refresh_token: Option<RefreshToken> = bind_query_as(sqlx::query_as(&query), &values)
+    let consumed: Option<RefreshToken> = bind_query_as(sqlx::query_as(&query), &values)
         .fetch_optional(db)
+        .instrument(query_span(&query))
         .await?;
 
-    if refresh_token.is_none() {
+    let refresh_token = match consumed {
+        Some(refresh_token) => refresh_token,
+        None => {
+            let reused = get_refresh_token(&refresh_token, db).await?;
+            crate::database::session::revoke_session(&reused.session_id, db).await?;
+
+            return Err(Error::new(
+                "REFRESH_TOKEN_REUSED",
+                hyper::StatusCode::UNAUTHORIZED,
+            ));
+        }
+    };
+
+    if refresh_token.expires_at < Utc::now() {
         return Err(Error::new(
-            "REFRESH_TOKEN_NOT_FOUND",
-            hyper::StatusCode::NOT_FOUND,
+            "REFRESH_TOKEN_EXPIRED",
+            hyper::StatusCode::UNAUTHORIZED,
         ));
     }
 
-    let refresh_token = refresh_token.unwrap();
-
-    if refresh_token.expires_at < chrono::Utc::now() {
+    if !crate::database::session::is_session_active(&refresh_token.session_id, db).await? {
         return Err(Error::new(
-            "REFRESH_TOKEN_EXPIRED",
+            "SESSION_REVOKED",
             hyper::StatusCode::UNAUTHORIZED,
         ));
     }
 
-    // Update the refresh token
-    let (query, values) = Query::update()
-        .table(RefreshTokenIden::Table)
-        .value(
-            RefreshTokenIden::ExpiresAt,
-            (chrono::Utc::now()
-                + chrono::Duration::seconds(AUTH_DEFAULT_REFRESH_TOKEN_EXPIRATION as i64))
-            .into(),
-        )
-        .value(RefreshTokenIden::UpdatedAt, chrono::Utc::now().into())
-        .and_where(Expr::col(RefreshTokenIden::Token).eq(refresh_token.token.clone()))
-        .to_owned()
-        .build(PostgresQueryBuilder);
-
-    let _refresh_token: Option<RefreshToken> = bind_query_as(sqlx::query_as(&query), &values)
-        .fetch_optional(db)
-        .await?;
-
     let user = get_user(
         &crate::models::user::Options {
             id: Some(refresh_token.user_id),
@@ -255,11 +548,283 @@ pub async fn refresh_token(refresh_token: String, db: &PgPool) -> Result<Refresh
     )
     .await?;
 
+    if user.is_suspended() {
+        return Err(Error::new("USER_SUSPENDED", hyper::StatusCode::FORBIDDEN));
+    }
+
+    let user_id = user.id.to_string();
+    let session_id = refresh_token.session_id;
+
+    let new_refresh_token = create_refresh_token(&user_id, &session_id, db).await?;
+    crate::database::session::extend_session(&session_id, refresh_token_expiry(), db).await?;
+
     // Create a new jwt token'
-    let jwt_token = create_token(user)?;
+    let jwt_token = create_token(user, &session_id, conf)?;
 
     // construct RefreshedToken
-    let token = RefreshedToken { token: jwt_token };
+    let token = RefreshedToken {
+        token: jwt_token,
+        refresh_token: new_refresh_token,
+        user_id,
+    };
 
     Ok(token)
 }
+
+/// Returns the refresh token row for the given token, whether or not it's still usable.
+pub async fn get_refresh_token(token: &str, db: &PgPool) -> Result<RefreshToken, Error> {
+    let (query, values) = Query::select()
+        .expr(Expr::asterisk())
+        .from(RefreshTokenIden::Table)
+        .and_where(Expr::col(RefreshTokenIden::Token).eq(token.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    let refresh_token: Option<RefreshToken> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    refresh_token.ok_or_else(|| Error::new("REFRESH_TOKEN_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
+}
+
+/// Creates a token the user can confirm their email address with.
+///
+/// # Returns
+/// * `String` - The token to be mailed to the user.
+pub async fn create_email_verification(user_id: &str, db: &PgPool) -> Result<String, Error> {
+    crate::database::user_token::create_user_token(
+        user_id,
+        UserTokenKind::EmailVerification,
+        AUTH_EMAIL_VERIFICATION_EXPIRATION,
+        db,
+    )
+    .await
+}
+
+/// Marks the email address of the user the token was created for as verified.
+pub async fn verify_email(token: &str, db: &PgPool) -> Result<User, Error> {
+    let user_token = crate::database::user_token::consume_user_token(
+        token,
+        UserTokenKind::EmailVerification,
+        db,
+    )
+    .await?;
+
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::EmailVerifiedAt, Utc::now().into());
+
+    update_user(&user_token.user_id, q, db).await
+}
+
+/// Creates a password reset token for the user with the given email.
+///
+/// # Returns
+/// * `Option<(User, String)>` - The user and the token to be mailed to them,
+///   `None` if no user has the email.
+pub async fn create_password_reset(
+    email: &str,
+    db: &PgPool,
+) -> Result<Option<(User, String)>, Error> {
+    let options = crate::models::user::Options {
+        id: None,
+        email: Some(email.to_string()),
+        page: None,
+        per_page: None,
+    };
+
+    let user = match get_user(&options, db).await {
+        Ok(user) => user,
+        Err(err) if err.status_code == hyper::StatusCode::NOT_FOUND => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    let token = crate::database::user_token::create_user_token(
+        &user.id.to_string(),
+        UserTokenKind::PasswordReset,
+        AUTH_PASSWORD_RESET_EXPIRATION,
+        db,
+    )
+    .await?;
+
+    Ok(Some((user, token)))
+}
+
+/// Sets a new password for the user the token was created for and revokes all of their sessions.
+pub async fn reset_password(token: &str, password: String, db: &PgPool) -> Result<User, Error> {
+    let user_token =
+        crate::database::user_token::consume_user_token(token, UserTokenKind::PasswordReset, db)
+            .await?;
+
+    set_password(&user_token.user_id, password, db).await
+}
+
+/// Sets a new password for the user with the given id and revokes all of their sessions.
+pub async fn set_password(id: &str, password: String, db: &PgPool) -> Result<User, Error> {
+    let password_hash = bcrypt::hash(password, bcrypt::DEFAULT_COST).unwrap();
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::PasswordHash, password_hash.into());
+
+    let user = update_user(id, q, db).await?;
+    crate::database::session::revoke_user_sessions(id, db).await?;
+
+    Ok(user)
+}
+
+/// Changes the access level of the user with the given id and revokes all of their sessions,
+/// so tokens carrying the old access level stop working.
+pub async fn update_access_level(
+    id: &str,
+    access_level: AccessLevel,
+    db: &PgPool,
+) -> Result<User, Error> {
+    let mut q = Query::update();
+    q.table(UserIden::Table).value_expr(
+        UserIden::AccessLevel,
+        Func::cast_as(access_level, Alias::new("Access_Level")),
+    );
+
+    let user = update_user(id, q, db).await?;
+    crate::database::session::revoke_user_sessions(id, db).await?;
+
+    Ok(user)
+}
+
+/// Suspends the user with the given id and revokes all of their sessions.
+///
+/// # Arguments
+/// * `until` - When the suspension ends, `None` bans the user indefinitely.
+pub async fn suspend_user(
+    id: &str,
+    reason: String,
+    until: Option<DateTime<Utc>>,
+    db: &PgPool,
+) -> Result<User, Error> {
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::SuspendedAt, Utc::now().into())
+        .value(UserIden::SuspendedUntil, until.into())
+        .value(UserIden::SuspensionReason, reason.into());
+
+    let user = update_user(id, q, db).await?;
+    crate::database::session::revoke_user_sessions(id, db).await?;
+
+    Ok(user)
+}
+
+/// Lifts the suspension of the user with the given id.
+pub async fn unsuspend_user(id: &str, db: &PgPool) -> Result<User, Error> {
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::SuspendedAt, Option::<DateTime<Utc>>::None.into())
+        .value(
+            UserIden::SuspendedUntil,
+            Option::<DateTime<Utc>>::None.into(),
+        )
+        .value(UserIden::SuspensionReason, Option::<String>::None.into());
+
+    update_user(id, q, db).await
+}
+
+/// Strips all personal data from the user with the given id while keeping the row,
+/// so anything referencing the user stays intact. The account can't be logged into afterwards,
+/// its sessions and API keys are revoked and its linked identities, TOTP secret and recovery
+/// codes are dropped. Audit log entries naming the user by email or username lose their target.
+pub async fn anonymize_user(id: &str, db: &PgPool) -> Result<User, Error> {
+    let mut q = Query::update();
+    q.table(UserIden::Table)
+        .value(UserIden::Username, format!("deleted-{id}").into())
+        .value(UserIden::Email, format!("{id}@deleted.invalid").into())
+        .value(UserIden::PasswordHash, "".into())
+        .value_expr(
+            UserIden::AccessLevel,
+            Func::cast_as(AccessLevel::User, Alias::new("Access_Level")),
+        )
+        .value(UserIden::SuspendedAt, Utc::now().into())
+        .value(
+            UserIden::SuspendedUntil,
+            Option::<DateTime<Utc>>::None.into(),
+        )
+        .value(UserIden::SuspensionReason, "Account deleted".into())
+        .value(UserIden::TotpSecret, Option::<String>::None.into())
+        .value(
+            UserIden::TotpEnabledAt,
+            Option::<DateTime<Utc>>::None.into(),
+        )
+        .value(UserIden::TotpLastStep, Option::<i64>::None.into());
+
+    let mut tx = db.begin().await?;
+
+    crate::database::audit_log::anonymize_user_targets(id, &mut tx).await?;
+    let user = update_user(id, q, &mut tx).await?;
+    crate::database::session::revoke_user_sessions(id, &mut tx).await?;
+    crate::database::api_key::revoke_user_api_keys(id, &mut tx).await?;
+    crate::database::oidc::delete_user_identities(id, &mut tx).await?;
+    crate::database::two_factor::delete_recovery_codes(id, &mut tx).await?;
+
+    tx.commit().await?;
+
+    Ok(user)
+}
+
+/// Permanently deletes the user with the given id along with their refresh tokens.
+pub async fn delete_user(id: &str, db: &PgPool) -> Result<(), Error> {
+    let mut tx = db.begin().await?;
+
+    let (query, values) = Query::delete()
+        .from_table(RefreshTokenIden::Table)
+        .and_where(Expr::col(RefreshTokenIden::UserId).eq(id.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(&mut tx)
+        .instrument(query_span(&query))
+        .await?;
+
+    let (query, values) = Query::delete()
+        .from_table(UserIden::Table)
+        .and_where(Expr::col(UserIden::Id).eq(id.to_string()))
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let result = bind_query(sqlx::query(&query), &values)
+        .execute(&mut tx)
+        .instrument(query_span(&query))
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(Error::new("USER_NOT_FOUND", hyper::StatusCode::NOT_FOUND));
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Runs the given update against the user with the given id, bumping `updated_at`.
+pub async fn update_user<'c>(
+    id: &str,
+    mut q: UpdateStatement,
+    db: impl Executor<'c, Database = Postgres>,
+) -> Result<User, Error> {
+    let (query, values) = q
+        .value(UserIden::UpdatedAt, Utc::now().into())
+        .and_where(Expr::col(UserIden::Id).eq(id.to_string()))
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let user: Option<User> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    user.ok_or_else(|| Error::new("USER_NOT_FOUND", hyper::StatusCode::NOT_FOUND))
+}
@@ -0,0 +1,104 @@
+use sea_query::{Alias, BinOper, Expr, Func, PostgresQueryBuilder, Query};
+use sqlx::{types::chrono::Utc, PgPool};
+use tracing::{debug, Instrument};
+
+use crate::{
+    database::query_span,
+    models::user_token::{UserToken, UserTokenIden, UserTokenKind},
+    sea_query_driver_postgres::{bind_query, bind_query_as},
+    utils::{
+        error::Error,
+        token::{generate_token, hash_token},
+    },
+};
+
+/// Creates a new token of the given kind for the user, invalidating any unused ones of the same kind.
+///
+/// # Arguments
+/// * `expires_in` - How many seconds the token stays valid for.
+/// # Returns
+/// * `String` - The token to be mailed to the user.
+pub async fn create_user_token(
+    user_id: &str,
+    kind: UserTokenKind,
+    expires_in: i64,
+    db: &PgPool,
+) -> Result<String, Error> {
+    let (query, values) = Query::update()
+        .table(UserTokenIden::Table)
+        .value(UserTokenIden::UsedAt, Utc::now().into())
+        .and_where(Expr::col(UserTokenIden::UserId).eq(user_id.to_string()))
+        .and_where(Expr::col(UserTokenIden::Kind).binary(
+            BinOper::Equal,
+            Func::cast_as(kind, Alias::new("user_token_kind")),
+        ))
+        .and_where(Expr::col(UserTokenIden::UsedAt).is_null())
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    let token = generate_token();
+    let (query, values) = Query::insert()
+        .into_table(UserTokenIden::Table)
+        .columns(vec![
+            UserTokenIden::UserId,
+            UserTokenIden::Kind,
+            UserTokenIden::TokenHash,
+            UserTokenIden::ExpiresAt,
+        ])
+        .exprs(vec![
+            Expr::val(user_id.to_string()).into(),
+            Func::cast_as(kind, Alias::new("user_token_kind")),
+            Expr::val(hash_token(&token)).into(),
+            Expr::val(Utc::now() + chrono::Duration::seconds(expires_in)).into(),
+        ])
+        .unwrap()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    bind_query(sqlx::query(&query), &values)
+        .execute(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    Ok(token)
+}
+
+/// Marks the token as used and returns it.
+///
+/// # Errors
+/// * `INVALID_TOKEN` - If the token doesn't exist, is of another kind, was already used or has expired.
+pub async fn consume_user_token(
+    token: &str,
+    kind: UserTokenKind,
+    db: &PgPool,
+) -> Result<UserToken, Error> {
+    let (query, values) = Query::update()
+        .table(UserTokenIden::Table)
+        .value(UserTokenIden::UsedAt, Utc::now().into())
+        .and_where(Expr::col(UserTokenIden::TokenHash).eq(hash_token(token)))
+        .and_where(Expr::col(UserTokenIden::Kind).binary(
+            BinOper::Equal,
+            Func::cast_as(kind, Alias::new("user_token_kind")),
+        ))
+        .and_where(Expr::col(UserTokenIden::UsedAt).is_null())
+        .and_where(Expr::col(UserTokenIden::ExpiresAt).gt(Utc::now()))
+        .returning_all()
+        .to_owned()
+        .build(PostgresQueryBuilder);
+
+    debug!("Query: {}", query);
+
+    let user_token: Option<UserToken> = bind_query_as(sqlx::query_as(&query), &values)
+        .fetch_optional(db)
+        .instrument(query_span(&query))
+        .await?;
+
+    user_token.ok_or_else(|| Error::new("INVALID_TOKEN", hyper::StatusCode::BAD_REQUEST))
+}
@@ -5,19 +5,19 @@ mod database;
 mod models;
 mod utils;
 
-use tracing::info;
-use utils::{config, startup};
+use clap::Parser;
+use utils::{
+    cli::{self, Cli},
+    telemetry,
+};
 
 sea_query::sea_query_driver_postgres!();
 
 #[tokio::main]
 async fn main() {
-    let conf = config::get_config();
-    let (server, addr) = startup::up(conf).await;
+    let cli = Cli::parse();
+    let code = cli::run(cli).await;
+    telemetry::shutdown();
 
-    info!("App is running on: http://{}", addr);
-
-    if let Err(err) = server.await {
-        eprintln!("Server error: {err}");
-    }
+    std::process::exit(code);
 }
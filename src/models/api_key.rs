@@ -0,0 +1,167 @@
+use async_graphql::{Enum, InputObject, Object, SimpleObject};
+use sea_query::{Iden, Value};
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    Decode, FromRow, Row,
+};
+
+#[derive(Enum, Clone, Debug, PartialEq, Eq, Copy, Decode)]
+pub enum ApiKeyScope {
+    /// The key can only run queries.
+    ReadOnly,
+    /// The key can run queries and mutations.
+    Write,
+}
+
+/// A personal API key, used by scripts and bots instead of logging in.
+///
+/// Only a hash of the key is stored, the key itself is shown once when it's created.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// The first few characters of the key, so users can tell their keys apart.
+    pub prefix: String,
+    pub key_hash: String,
+    pub scope: ApiKeyScope,
+    /// Leave empty for keys that never expire.
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(InputObject)]
+pub struct NewApiKey {
+    pub name: String,
+    pub scope: ApiKeyScope,
+    /// Leave empty for a key that never expires.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(SimpleObject)]
+pub struct CreatedApiKey {
+    /// The key to put in the Authorization header. It can't be retrieved again.
+    pub key: String,
+    pub api_key: ApiKey,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    /// Only return keys that are neither revoked nor expired.
+    pub active: bool,
+}
+
+#[allow(dead_code)]
+pub enum ApiKeyIden {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Prefix,
+    KeyHash,
+    Scope,
+    ExpiresAt,
+    LastUsedAt,
+    RevokedAt,
+    CreatedAt,
+}
+
+impl Iden for ApiKeyIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                ApiKeyIden::Table => "api_keys",
+                ApiKeyIden::Id => "id",
+                ApiKeyIden::UserId => "user_id",
+                ApiKeyIden::Name => "name",
+                ApiKeyIden::Prefix => "prefix",
+                ApiKeyIden::KeyHash => "key_hash",
+                ApiKeyIden::Scope => "scope",
+                ApiKeyIden::ExpiresAt => "expires_at",
+                ApiKeyIden::LastUsedAt => "last_used_at",
+                ApiKeyIden::RevokedAt => "revoked_at",
+                ApiKeyIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for ApiKey {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            prefix: row.try_get("prefix")?,
+            key_hash: row.try_get("key_hash")?,
+            scope: row.try_get("scope")?,
+            expires_at: row.try_get("expires_at")?,
+            last_used_at: row.try_get("last_used_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[Object]
+impl ApiKey {
+    async fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    async fn scope(&self) -> ApiKeyScope {
+        self.scope
+    }
+
+    async fn expires_at(&self) -> Option<DateTime<Utc>> {
+        self.expires_at
+    }
+
+    async fn last_used_at(&self) -> Option<DateTime<Utc>> {
+        self.last_used_at
+    }
+
+    async fn revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl sqlx::Type<sqlx::Postgres> for ApiKeyScope {
+    fn type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("api_key_scope")
+    }
+}
+
+impl From<ApiKeyScope> for Value {
+    fn from(scope: ApiKeyScope) -> Self {
+        match scope {
+            ApiKeyScope::ReadOnly => "ReadOnly".into(),
+            ApiKeyScope::Write => "Write".into(),
+        }
+    }
+}
@@ -1,14 +1,17 @@
 use super::{ExternalSite, Name};
 use async_graphql::Object;
 use sea_query::Iden;
+use serde::Serialize;
 use sqlx::{
     postgres::PgRow,
     types::chrono::{DateTime, Utc},
     Decode, FromRow, Row,
 };
 use ulid::Ulid;
+use utoipa::ToSchema;
 
-#[derive(async_graphql::Enum, Clone, Debug, PartialEq, Eq, Copy, Decode)]
+#[derive(async_graphql::Enum, Clone, Debug, PartialEq, Eq, Copy, Decode, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ArtistType {
     /// Indicates that the artist is a single person.
     Solo,
@@ -24,9 +27,10 @@ pub enum ArtistType {
     Other,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, ToSchema)]
 pub struct Artist {
     /// Unique ID of the artist.
+    #[schema(value_type = String)]
     pub id: Ulid,
     /// Contains the name of the artist.
     pub name: Name,
@@ -46,6 +50,7 @@ pub struct Artist {
     /// For example, a group of people will be displayed differently than a single person.
     pub artist_type: ArtistType,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub join_phrase: Option<String>,
 }
 
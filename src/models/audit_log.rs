@@ -0,0 +1,239 @@
+use async_graphql::{Enum, Object};
+use sea_query::{Iden, Value};
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    Decode, FromRow, Row,
+};
+
+#[derive(Enum, Clone, Debug, PartialEq, Eq, Copy, Decode)]
+pub enum AuditAction {
+    /// A user logged in successfully.
+    Login,
+    /// Someone tried to log in with invalid credentials.
+    LoginFailed,
+    /// A new user registered.
+    Register,
+    /// A refresh token was exchanged for a new access token.
+    RefreshToken,
+    /// An admin changed the access level of a user.
+    AccessLevelChange,
+    /// An admin suspended or banned a user.
+    Suspend,
+    /// An admin lifted a suspension.
+    Unsuspend,
+    /// An admin deleted a user.
+    DeleteUser,
+    /// An admin anonymized a user.
+    AnonymizeUser,
+    /// A user logged out of a session.
+    Logout,
+    /// A session was revoked from the session list.
+    RevokeSession,
+    /// An already rotated refresh token was presented again, revoking its session.
+    RefreshTokenReuse,
+    /// A user verified their email address.
+    EmailVerified,
+    /// Someone asked for a password reset mail.
+    PasswordResetRequested,
+    /// A user reset their password with a mailed token.
+    PasswordReset,
+    /// A user created an API key.
+    CreateApiKey,
+    /// An API key was revoked.
+    RevokeApiKey,
+    /// An account at an OpenID Connect provider was linked to an existing user.
+    LinkIdentity,
+    /// A user enabled two-factor authentication.
+    TwoFactorEnabled,
+    /// Two-factor authentication was disabled for a user.
+    TwoFactorDisabled,
+    /// Someone entered a wrong two-factor code while logging in.
+    TwoFactorFailed,
+    /// A user replaced their recovery codes.
+    RecoveryCodesRegenerated,
+    /// An admin added a query to the persisted queries.
+    RegisterPersistedQuery,
+    /// An admin removed a persisted query.
+    DeletePersistedQuery,
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    pub id: i32,
+    /// ID of the user who performed the action, if known.
+    pub actor_id: Option<String>,
+    pub action: AuditAction,
+    /// Whatever the action was performed on, e.g. a user ID or the attempted username.
+    pub target: Option<String>,
+    /// Remote address of the client that sent the request.
+    pub ip_address: Option<String>,
+    pub details: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct NewAuditLog {
+    pub actor_id: Option<String>,
+    pub action: AuditAction,
+    pub target: Option<String>,
+    pub ip_address: Option<String>,
+    pub details: Option<String>,
+}
+
+impl NewAuditLog {
+    pub fn new(action: AuditAction) -> Self {
+        Self {
+            actor_id: None,
+            action,
+            target: None,
+            ip_address: None,
+            details: None,
+        }
+    }
+
+    pub fn actor_id(mut self, actor_id: String) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn target(mut self, target: String) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn ip_address(mut self, ip_address: Option<String>) -> Self {
+        self.ip_address = ip_address;
+        self
+    }
+
+    pub fn details(mut self, details: String) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub actor_id: Option<String>,
+    pub action: Option<AuditAction>,
+    pub target: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+#[allow(dead_code)]
+pub enum AuditLogIden {
+    Table,
+    Id,
+    ActorId,
+    Action,
+    Target,
+    IpAddress,
+    Details,
+    CreatedAt,
+}
+
+impl Iden for AuditLogIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                AuditLogIden::Table => "audit_logs",
+                AuditLogIden::Id => "id",
+                AuditLogIden::ActorId => "actor_id",
+                AuditLogIden::Action => "action",
+                AuditLogIden::Target => "target",
+                AuditLogIden::IpAddress => "ip_address",
+                AuditLogIden::Details => "details",
+                AuditLogIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for AuditLog {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            actor_id: row.try_get("actor_id")?,
+            action: row.try_get("action")?,
+            target: row.try_get("target")?,
+            ip_address: row.try_get("ip_address")?,
+            details: row.try_get("details")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+#[Object]
+impl AuditLog {
+    async fn id(&self) -> i32 {
+        self.id
+    }
+
+    async fn actor_id(&self) -> Option<&str> {
+        self.actor_id.as_deref()
+    }
+
+    async fn action(&self) -> AuditAction {
+        self.action
+    }
+
+    async fn target(&self) -> Option<&str> {
+        self.target.as_deref()
+    }
+
+    async fn ip_address(&self) -> Option<&str> {
+        self.ip_address.as_deref()
+    }
+
+    async fn details(&self) -> Option<&str> {
+        self.details.as_deref()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+}
+
+impl sqlx::Type<sqlx::Postgres> for AuditAction {
+    fn type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("audit_action")
+    }
+}
+
+impl From<AuditAction> for Value {
+    fn from(action: AuditAction) -> Self {
+        match action {
+            AuditAction::Login => "Login".into(),
+            AuditAction::LoginFailed => "LoginFailed".into(),
+            AuditAction::Register => "Register".into(),
+            AuditAction::RefreshToken => "RefreshToken".into(),
+            AuditAction::AccessLevelChange => "AccessLevelChange".into(),
+            AuditAction::Suspend => "Suspend".into(),
+            AuditAction::Unsuspend => "Unsuspend".into(),
+            AuditAction::DeleteUser => "DeleteUser".into(),
+            AuditAction::AnonymizeUser => "AnonymizeUser".into(),
+            AuditAction::Logout => "Logout".into(),
+            AuditAction::RevokeSession => "RevokeSession".into(),
+            AuditAction::RefreshTokenReuse => "RefreshTokenReuse".into(),
+            AuditAction::EmailVerified => "EmailVerified".into(),
+            AuditAction::PasswordResetRequested => "PasswordResetRequested".into(),
+            AuditAction::PasswordReset => "PasswordReset".into(),
+            AuditAction::CreateApiKey => "CreateApiKey".into(),
+            AuditAction::RevokeApiKey => "RevokeApiKey".into(),
+            AuditAction::LinkIdentity => "LinkIdentity".into(),
+            AuditAction::TwoFactorEnabled => "TwoFactorEnabled".into(),
+            AuditAction::TwoFactorDisabled => "TwoFactorDisabled".into(),
+            AuditAction::TwoFactorFailed => "TwoFactorFailed".into(),
+            AuditAction::RecoveryCodesRegenerated => "RecoveryCodesRegenerated".into(),
+            AuditAction::RegisterPersistedQuery => "RegisterPersistedQuery".into(),
+            AuditAction::DeletePersistedQuery => "DeletePersistedQuery".into(),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use async_graphql::{Enum, SimpleObject};
+use serde::Deserialize;
+
+#[derive(Enum, Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum EntityKind {
+    Song,
+    Artist,
+    Release,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ChangeOperation {
+    Insert,
+    Update,
+}
+
+/// A song, artist or release that was added or changed, as sent by the
+/// `notify_entity_change` trigger.
+#[derive(SimpleObject, Clone, Debug, Deserialize)]
+pub struct EntityChange {
+    pub kind: EntityKind,
+    #[graphql(skip)]
+    pub operation: ChangeOperation,
+    pub id: String,
+}
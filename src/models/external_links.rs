@@ -1,7 +1,15 @@
 use async_graphql::{Enum, Object};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
 use std::error::Error;
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    ToSchema,
+};
 
-#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, sqlx::Encode, sqlx::Decode)]
+#[derive(
+    Enum, Copy, Clone, Debug, Eq, PartialEq, sqlx::Encode, sqlx::Decode, Serialize, ToSchema,
+)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ExternalSiteType {
     AppleMusic,
     YouTube,
@@ -33,6 +41,13 @@ impl ExternalSite {
     }
 
     pub async fn url(&self) -> String {
+        self.link()
+    }
+}
+
+impl ExternalSite {
+    /// Link to the song, release or artist on the site.
+    pub fn link(&self) -> String {
         // Figure out what the parent type is and return the correct URL
         // based on the site type.
         match self.site {
@@ -63,6 +78,35 @@ impl ExternalSite {
     }
 }
 
+impl Serialize for ExternalSite {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ExternalSite", 2)?;
+        state.serialize_field("site_type", &self.site)?;
+        state.serialize_field("url", &self.link())?;
+        state.end()
+    }
+}
+
+// Implementing ToSchema for ExternalSite
+//
+// This is required for the OpenAPI document to describe ExternalSite as it's serialized.
+impl<'s> ToSchema<'s> for ExternalSite {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .property("site_type", ExternalSiteType::schema().1)
+            .required("site_type")
+            .property(
+                "url",
+                ObjectBuilder::new()
+                    .schema_type(SchemaType::String)
+                    .description(Some("Link to the song, release or artist on the site.")),
+            )
+            .required("url");
+
+        ("ExternalSite", schema.into())
+    }
+}
+
 // Implementing Decode for ExternalSites
 //
 // This is required for ExternalSites to be decoded properly.
@@ -1,9 +1,17 @@
+pub mod api_key;
 pub mod artist;
+pub mod audit_log;
+pub mod entity_change;
+pub mod oidc;
+pub mod persisted_query;
 pub mod refresh_token;
 pub mod release;
+pub mod session;
 pub mod song;
 pub mod tag;
+pub mod two_factor;
 pub mod user;
+pub mod user_token;
 
 pub mod external_links;
 
@@ -15,15 +23,17 @@ use std::error::Error;
 use async_graphql::{InputObject, Object};
 
 use sea_query::Value;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug, InputObject)]
+#[derive(Clone, Debug, InputObject, Deserialize, ToSchema)]
 pub struct NewName {
     pub native: Option<String>,
     pub romanized: Option<String>,
     pub english: Option<String>,
 }
 
-#[derive(Clone, Debug, sqlx::Encode)]
+#[derive(Clone, Debug, sqlx::Encode, Serialize, ToSchema)]
 pub struct Name {
     /// Native name the original variant uses.
     ///
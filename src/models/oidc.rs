@@ -0,0 +1,138 @@
+use async_graphql::{InputObject, SimpleObject};
+use sea_query::Iden;
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    FromRow, Row,
+};
+
+/// A login that was started at an OpenID Connect provider, looked up again by its state
+/// once the user is redirected back.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct OidcState {
+    pub state: String,
+    /// Name of the provider from the config.
+    pub provider: String,
+    pub nonce: String,
+    /// The PKCE secret, proves to the provider that the code is redeemed by whoever asked for it.
+    pub code_verifier: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An account at an OpenID Connect provider that is linked to a user.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct UserIdentity {
+    pub provider: String,
+    /// ID of the user at the provider.
+    pub subject: String,
+    pub user_id: String,
+    /// Email address the provider reported at the last login.
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: DateTime<Utc>,
+}
+
+#[derive(SimpleObject)]
+pub struct OidcAuthorization {
+    /// Where to send the user to log in at the provider.
+    pub url: String,
+    /// Has to be handed back along with the code once the provider redirects back.
+    pub state: String,
+}
+
+#[derive(InputObject)]
+pub struct OidcLogin {
+    /// The `state` query parameter the provider redirected back with.
+    pub state: String,
+    /// The `code` query parameter the provider redirected back with.
+    pub code: String,
+}
+
+#[allow(dead_code)]
+pub enum OidcStateIden {
+    Table,
+    State,
+    Provider,
+    Nonce,
+    CodeVerifier,
+    ExpiresAt,
+    CreatedAt,
+}
+
+impl Iden for OidcStateIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                OidcStateIden::Table => "oidc_states",
+                OidcStateIden::State => "state",
+                OidcStateIden::Provider => "provider",
+                OidcStateIden::Nonce => "nonce",
+                OidcStateIden::CodeVerifier => "code_verifier",
+                OidcStateIden::ExpiresAt => "expires_at",
+                OidcStateIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+#[allow(dead_code)]
+pub enum UserIdentityIden {
+    Table,
+    Provider,
+    Subject,
+    UserId,
+    Email,
+    CreatedAt,
+    LastLoginAt,
+}
+
+impl Iden for UserIdentityIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                UserIdentityIden::Table => "user_identities",
+                UserIdentityIden::Provider => "provider",
+                UserIdentityIden::Subject => "subject",
+                UserIdentityIden::UserId => "user_id",
+                UserIdentityIden::Email => "email",
+                UserIdentityIden::CreatedAt => "created_at",
+                UserIdentityIden::LastLoginAt => "last_login_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for OidcState {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            state: row.try_get("state")?,
+            provider: row.try_get("provider")?,
+            nonce: row.try_get("nonce")?,
+            code_verifier: row.try_get("code_verifier")?,
+            expires_at: row.try_get("expires_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for UserIdentity {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            provider: row.try_get("provider")?,
+            subject: row.try_get("subject")?,
+            user_id: row.try_get("user_id")?,
+            email: row.try_get("email")?,
+            created_at: row.try_get("created_at")?,
+            last_login_at: row.try_get("last_login_at")?,
+        })
+    }
+}
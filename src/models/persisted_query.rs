@@ -0,0 +1,56 @@
+use async_graphql::SimpleObject;
+use sea_query::Iden;
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    FromRow, Row,
+};
+
+/// A query clients can run by sending only its hash.
+#[derive(Clone, Debug, SimpleObject)]
+pub struct PersistedQuery {
+    /// Hex encoded SHA-256 hash of the query.
+    pub hash: String,
+    pub query: String,
+    /// Registered by an admin, rather than by a client sending it along with its hash.
+    /// Only these are accepted when the allow-list is enabled.
+    pub allow_listed: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[allow(dead_code)]
+pub enum PersistedQueryIden {
+    Table,
+    Hash,
+    Query,
+    AllowListed,
+    CreatedAt,
+}
+
+impl Iden for PersistedQueryIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                PersistedQueryIden::Table => "persisted_queries",
+                PersistedQueryIden::Hash => "hash",
+                PersistedQueryIden::Query => "query",
+                PersistedQueryIden::AllowListed => "allow_listed",
+                PersistedQueryIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for PersistedQuery {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            hash: row.try_get("hash")?,
+            query: row.try_get("query")?,
+            allow_listed: row.try_get("allow_listed")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
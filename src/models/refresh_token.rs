@@ -9,6 +9,7 @@ use sqlx::{
 pub struct RefreshToken {
     pub id: i32,
     pub user_id: String,
+    pub session_id: String,
     pub token: String,
     pub expires_at: DateTime<Utc>,
     pub revoked: bool,
@@ -20,6 +21,7 @@ impl<'r> FromRow<'r, PgRow> for RefreshToken {
     fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
         let id: i32 = row.try_get("id")?;
         let user_id: String = row.try_get("user_id")?;
+        let session_id: String = row.try_get("session_id")?;
         let token: String = row.try_get("token")?;
         let expires_at: DateTime<Utc> = row.try_get("expires_at")?;
         let revoked: bool = row.try_get("revoked")?;
@@ -29,6 +31,7 @@ impl<'r> FromRow<'r, PgRow> for RefreshToken {
         Ok(Self {
             id,
             user_id,
+            session_id,
             token,
             expires_at,
             revoked,
@@ -43,6 +46,7 @@ pub enum RefreshTokenIden {
     Table,
     Id,
     UserId,
+    SessionId,
     Token,
     ExpiresAt,
     Revoked,
@@ -59,6 +63,7 @@ impl sea_query::Iden for RefreshTokenIden {
                 RefreshTokenIden::Table => "refresh_tokens",
                 RefreshTokenIden::Id => "id",
                 RefreshTokenIden::UserId => "user_id",
+                RefreshTokenIden::SessionId => "session_id",
                 RefreshTokenIden::Token => "token",
                 RefreshTokenIden::ExpiresAt => "expires_at",
                 RefreshTokenIden::Revoked => "revoked",
@@ -73,6 +78,10 @@ impl sea_query::Iden for RefreshTokenIden {
 #[derive(Clone, Debug, SimpleObject)]
 pub struct RefreshedToken {
     pub token: String,
+    /// The refresh token replacing the one that was used, which can't be used again.
+    pub refresh_token: String,
+    #[graphql(skip)]
+    pub user_id: String,
 }
 
 #[derive(Clone, Debug, InputObject)]
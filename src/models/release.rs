@@ -1,25 +1,30 @@
 use super::tag::Tag;
 use super::{ExternalSite, Name};
+use crate::{constants::QUERY_COST_LIST_SIZE, utils::query_limits::list_cost};
 use async_graphql::{Context, Enum, Object};
+use serde::Serialize;
 use sqlx::types::chrono::NaiveDate;
 use sqlx::PgPool;
 use sqlx::{postgres::PgRow, Decode, FromRow, Row};
 use ulid::Ulid;
+use utoipa::ToSchema;
 
-#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Decode)]
+#[derive(Enum, Copy, Clone, Debug, Eq, PartialEq, Decode, Serialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum ReleaseType {
     Album,
     Single,
     EP,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, ToSchema)]
 /// Release done by one or multiple artist
 ///
 /// This structure simply represents an album but has a fancy name to not to
 /// confuse it with [`ReleaseType::Album`]
 pub struct Release {
     /// Unique ID of the release
+    #[schema(value_type = String)]
     pub id: Ulid,
     /// Name of the release
     pub name: Name,
@@ -178,6 +183,7 @@ impl Release {
         self.script_language.as_ref()
     }
 
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
     async fn tags<'ctx>(&self, context: &Context<'ctx>) -> Vec<Tag> {
         let db = context.data_unchecked::<PgPool>();
         let options = crate::models::tag::Options::new().release_id(self.id);
@@ -0,0 +1,124 @@
+use async_graphql::{Context, Object};
+use sea_query::Iden;
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    FromRow, Row,
+};
+
+use crate::utils::middleware::Claims;
+
+/// A login of a user on a device, kept alive by rotating its refresh tokens.
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: String,
+    pub user_id: String,
+    /// Remote address of the client when the session was created.
+    pub ip_address: Option<String>,
+    /// User agent of the client when the session was created.
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    /// Only return sessions that are neither revoked nor expired.
+    pub active: bool,
+}
+
+#[allow(dead_code)]
+pub enum SessionIden {
+    Table,
+    Id,
+    UserId,
+    IpAddress,
+    UserAgent,
+    CreatedAt,
+    LastUsedAt,
+    ExpiresAt,
+    RevokedAt,
+}
+
+impl Iden for SessionIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                SessionIden::Table => "sessions",
+                SessionIden::Id => "id",
+                SessionIden::UserId => "user_id",
+                SessionIden::IpAddress => "ip_address",
+                SessionIden::UserAgent => "user_agent",
+                SessionIden::CreatedAt => "created_at",
+                SessionIden::LastUsedAt => "last_used_at",
+                SessionIden::ExpiresAt => "expires_at",
+                SessionIden::RevokedAt => "revoked_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for Session {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            ip_address: row.try_get("ip_address")?,
+            user_agent: row.try_get("user_agent")?,
+            created_at: row.try_get("created_at")?,
+            last_used_at: row.try_get("last_used_at")?,
+            expires_at: row.try_get("expires_at")?,
+            revoked_at: row.try_get("revoked_at")?,
+        })
+    }
+}
+
+#[Object]
+impl Session {
+    async fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    async fn ip_address(&self) -> Option<&str> {
+        self.ip_address.as_deref()
+    }
+
+    async fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+
+    /// Whether this is the session the request was made with.
+    async fn current<'ctx>(&self, context: &Context<'ctx>) -> bool {
+        context
+            .data_opt::<Claims>()
+            .map(|claims| claims.sid == self.id)
+            .unwrap_or(false)
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    async fn last_used_at(&self) -> DateTime<Utc> {
+        self.last_used_at
+    }
+
+    async fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+
+    async fn revoked_at(&self) -> Option<DateTime<Utc>> {
+        self.revoked_at
+    }
+}
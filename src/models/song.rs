@@ -1,12 +1,15 @@
 use super::{artist::Artist, release::Release, tag::Tag, ExternalSite, Name, NewName};
+use crate::{constants::QUERY_COST_LIST_SIZE, utils::query_limits::list_cost};
 use async_graphql::{Context, InputObject, Object};
 use sea_query::Iden;
+use serde::{Deserialize, Serialize};
 use sqlx::{postgres::PgRow, types::chrono::NaiveDate, FromRow, PgPool, Row};
 use ulid::Ulid;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug)]
-
+#[derive(Clone, Debug, Serialize, ToSchema)]
 pub struct Song {
+    #[schema(value_type = String)]
     pub id: Ulid,
     pub name: Name,
     pub external_sites: Option<Vec<ExternalSite>>,
@@ -52,6 +55,7 @@ impl Song {
         &self.name
     }
 
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
     async fn artists<'ctx>(&self, context: &Context<'ctx>) -> Vec<Artist> {
         let db = context.data_unchecked::<PgPool>();
         crate::database::artist::get_artists_by_song_id(&self.id, db)
@@ -59,6 +63,7 @@ impl Song {
             .unwrap()
     }
 
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
     async fn releases<'ctx>(&self, context: &Context<'ctx>) -> Vec<Release> {
         let db = context.data_unchecked::<PgPool>();
         crate::database::release::get_releases_by_song_id(&self.id, db)
@@ -66,6 +71,7 @@ impl Song {
             .unwrap()
     }
 
+    #[graphql(complexity = "list_cost(QUERY_COST_LIST_SIZE, child_complexity)")]
     async fn tags<'ctx>(&self, context: &Context<'ctx>) -> Vec<Tag> {
         let db = context.data_unchecked::<PgPool>();
         let options = crate::models::tag::Options::new().song_id(self.id);
@@ -103,7 +109,7 @@ impl<'r> FromRow<'r, PgRow> for Song {
     }
 }
 
-#[derive(Clone, Debug, InputObject)]
+#[derive(Clone, Debug, InputObject, Deserialize, ToSchema)]
 pub struct NewSong {
     pub name: NewName,
     pub artists: Vec<String>,
@@ -1,15 +1,16 @@
 use async_graphql::{InputObject, Object};
 
 use sea_query::Iden;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use sqlx::{
     postgres::PgRow,
     types::chrono::{DateTime, Utc},
     FromRow, Row,
 };
 use ulid::Ulid;
+use utoipa::ToSchema;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, ToSchema)]
 pub struct Tag {
     pub id: i32,
     pub name: String,
@@ -0,0 +1,38 @@
+use async_graphql::SimpleObject;
+use sea_query::Iden;
+
+#[derive(SimpleObject)]
+pub struct TwoFactorSetup {
+    /// Base32 encoded secret, for entering it into the authenticator app by hand.
+    pub secret: String,
+    /// `otpauth://` URL to show as a QR code.
+    pub otpauth_url: String,
+}
+
+#[allow(dead_code)]
+pub enum RecoveryCodeIden {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    UsedAt,
+    CreatedAt,
+}
+
+impl Iden for RecoveryCodeIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                RecoveryCodeIden::Table => "recovery_codes",
+                RecoveryCodeIden::Id => "id",
+                RecoveryCodeIden::UserId => "user_id",
+                RecoveryCodeIden::CodeHash => "code_hash",
+                RecoveryCodeIden::UsedAt => "used_at",
+                RecoveryCodeIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
@@ -10,7 +10,7 @@ use sqlx::{
 use tracing::debug;
 use ulid::Ulid;
 
-use crate::utils::{error::Error, middleware::Claims};
+use crate::utils::{config, error::Error, middleware::Claims};
 
 #[derive(
     async_graphql::Enum,
@@ -40,6 +40,19 @@ pub struct User {
     pub access_level: AccessLevel,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// When the user was suspended, `None` if they aren't.
+    pub suspended_at: Option<DateTime<Utc>>,
+    /// When the suspension ends, `None` for an indefinite ban.
+    pub suspended_until: Option<DateTime<Utc>>,
+    pub suspension_reason: Option<String>,
+    /// When the user confirmed their email address, `None` if they haven't yet.
+    pub email_verified_at: Option<DateTime<Utc>>,
+    /// Base32 encoded TOTP secret, set once the user starts enabling two-factor authentication.
+    pub totp_secret: Option<String>,
+    /// When the user finished enabling two-factor authentication, `None` if they haven't.
+    pub totp_enabled_at: Option<DateTime<Utc>>,
+    /// Time step of the last accepted TOTP code.
+    pub totp_last_step: Option<i64>,
 }
 
 #[derive(async_graphql::InputObject)]
@@ -56,6 +69,29 @@ pub struct Register {
     pub password: String,
 }
 
+#[derive(async_graphql::InputObject)]
+pub struct SuspendUser {
+    pub id: String,
+    pub reason: String,
+    /// Leave empty to ban the user indefinitely.
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(async_graphql::InputObject)]
+pub struct VerifyTwoFactor {
+    /// The `twoFactorToken` from the login response.
+    pub token: String,
+    /// A code from the authenticator app or one of the recovery codes.
+    pub code: String,
+}
+
+#[derive(async_graphql::InputObject)]
+pub struct ResetPassword {
+    /// The token from the password reset mail.
+    pub token: String,
+    pub password: String,
+}
+
 impl User {
     pub fn new(
         username: String,
@@ -76,7 +112,37 @@ impl User {
             access_level,
             created_at,
             updated_at,
+            suspended_at: None,
+            suspended_until: None,
+            suspension_reason: None,
+            email_verified_at: None,
+            totp_secret: None,
+            totp_enabled_at: None,
+            totp_last_step: None,
+        }
+    }
+
+    /// Whether the user is currently suspended or banned.
+    pub fn is_suspended(&self) -> bool {
+        match (self.suspended_at, self.suspended_until) {
+            (Some(_), Some(until)) => until > Utc::now(),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    pub fn has_two_factor(&self) -> bool {
+        self.totp_enabled_at.is_some()
+    }
+
+    /// The access level the user's tokens carry. Users whose access level requires
+    /// two-factor authentication only act as a `User` until they enable it.
+    pub fn effective_access_level(&self, two_factor: &config::TwoFactor) -> AccessLevel {
+        if two_factor.required_for.contains(&self.access_level) && !self.has_two_factor() {
+            return AccessLevel::User;
         }
+
+        self.access_level
     }
 }
 
@@ -89,6 +155,13 @@ impl<'r> FromRow<'r, PgRow> for User {
         let access_level: AccessLevel = row.try_get("access_level")?;
         let created_at: DateTime<Utc> = row.try_get("created_at")?;
         let updated_at: DateTime<Utc> = row.try_get("updated_at")?;
+        let suspended_at: Option<DateTime<Utc>> = row.try_get("suspended_at")?;
+        let suspended_until: Option<DateTime<Utc>> = row.try_get("suspended_until")?;
+        let suspension_reason: Option<String> = row.try_get("suspension_reason")?;
+        let email_verified_at: Option<DateTime<Utc>> = row.try_get("email_verified_at")?;
+        let totp_secret: Option<String> = row.try_get("totp_secret")?;
+        let totp_enabled_at: Option<DateTime<Utc>> = row.try_get("totp_enabled_at")?;
+        let totp_last_step: Option<i64> = row.try_get("totp_last_step")?;
 
         Ok(Self {
             id: id.parse().unwrap(),
@@ -98,6 +171,13 @@ impl<'r> FromRow<'r, PgRow> for User {
             access_level,
             created_at,
             updated_at,
+            suspended_at,
+            suspended_until,
+            suspension_reason,
+            email_verified_at,
+            totp_secret,
+            totp_enabled_at,
+            totp_last_step,
         })
     }
 }
@@ -108,7 +188,7 @@ impl serde::Serialize for User {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("User", 7)?;
+        let mut state = serializer.serialize_struct("User", 14)?;
         state.serialize_field("id", &self.id.to_string())?;
         state.serialize_field("username", &self.username)?;
         state.serialize_field("email", &self.email)?;
@@ -116,6 +196,22 @@ impl serde::Serialize for User {
         state.serialize_field("access_level", &self.access_level)?;
         state.serialize_field("created_at", &self.created_at.to_rfc3339())?;
         state.serialize_field("updated_at", &self.updated_at.to_rfc3339())?;
+        state.serialize_field("suspended_at", &self.suspended_at.map(|t| t.to_rfc3339()))?;
+        state.serialize_field(
+            "suspended_until",
+            &self.suspended_until.map(|t| t.to_rfc3339()),
+        )?;
+        state.serialize_field("suspension_reason", &self.suspension_reason)?;
+        state.serialize_field(
+            "email_verified_at",
+            &self.email_verified_at.map(|t| t.to_rfc3339()),
+        )?;
+        state.serialize_field("totp_secret", &self.totp_secret)?;
+        state.serialize_field(
+            "totp_enabled_at",
+            &self.totp_enabled_at.map(|t| t.to_rfc3339()),
+        )?;
+        state.serialize_field("totp_last_step", &self.totp_last_step)?;
         state.end()
     }
 }
@@ -135,6 +231,19 @@ impl<'de> serde::Deserialize<'de> for User {
             access_level: AccessLevel,
             created_at: String,
             updated_at: String,
+            suspended_at: Option<String>,
+            suspended_until: Option<String>,
+            suspension_reason: Option<String>,
+            email_verified_at: Option<String>,
+            totp_secret: Option<String>,
+            totp_enabled_at: Option<String>,
+            totp_last_step: Option<i64>,
+        }
+
+        fn parse(date: &str) -> DateTime<Utc> {
+            DateTime::parse_from_rfc3339(date)
+                .unwrap()
+                .with_timezone(&Utc)
         }
 
         let visitor = UserVisitor::deserialize(deserializer)?;
@@ -144,12 +253,15 @@ impl<'de> serde::Deserialize<'de> for User {
             email: visitor.email,
             password_hash: visitor.password_hash,
             access_level: visitor.access_level,
-            created_at: DateTime::parse_from_rfc3339(&visitor.created_at)
-                .unwrap()
-                .with_timezone(&Utc),
-            updated_at: DateTime::parse_from_rfc3339(&visitor.updated_at)
-                .unwrap()
-                .with_timezone(&Utc),
+            created_at: parse(&visitor.created_at),
+            updated_at: parse(&visitor.updated_at),
+            suspended_at: visitor.suspended_at.as_deref().map(parse),
+            suspended_until: visitor.suspended_until.as_deref().map(parse),
+            suspension_reason: visitor.suspension_reason,
+            email_verified_at: visitor.email_verified_at.as_deref().map(parse),
+            totp_secret: visitor.totp_secret,
+            totp_enabled_at: visitor.totp_enabled_at.as_deref().map(parse),
+            totp_last_step: visitor.totp_last_step,
         })
     }
 }
@@ -164,6 +276,13 @@ pub enum UserIden {
     AccessLevel,
     CreatedAt,
     UpdatedAt,
+    SuspendedAt,
+    SuspendedUntil,
+    SuspensionReason,
+    EmailVerifiedAt,
+    TotpSecret,
+    TotpEnabledAt,
+    TotpLastStep,
 }
 
 impl sea_query::Iden for UserIden {
@@ -180,6 +299,13 @@ impl sea_query::Iden for UserIden {
                 UserIden::AccessLevel => "access_level",
                 UserIden::CreatedAt => "created_at",
                 UserIden::UpdatedAt => "updated_at",
+                UserIden::SuspendedAt => "suspended_at",
+                UserIden::SuspendedUntil => "suspended_until",
+                UserIden::SuspensionReason => "suspension_reason",
+                UserIden::EmailVerifiedAt => "email_verified_at",
+                UserIden::TotpSecret => "totp_secret",
+                UserIden::TotpEnabledAt => "totp_enabled_at",
+                UserIden::TotpLastStep => "totp_last_step",
             }
         )
         .unwrap();
@@ -197,22 +323,43 @@ impl User {
     }
 
     async fn email<'ctx>(&self, context: &Context<'ctx>) -> Result<&str, Error> {
-        // Only return email if user is admin
-        // or if user is the same as the user being queried
-        if let Some(user) = context.data_opt::<Claims>() {
-            debug!("User:{:?}", user);
-            if user.access_level == AccessLevel::Admin
-                || Ulid::from_string(&user.ulid).unwrap() == self.id
-            {
-                return Ok(self.email.as_str());
-            } else {
-                // return Not authorized error
-                Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED))
-            }
-        } else {
-            // return Not authorized error
-            Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED))
-        }
+        self.authorize_private(context)?;
+        Ok(self.email.as_str())
+    }
+
+    async fn email_verified<'ctx>(&self, context: &Context<'ctx>) -> Result<bool, Error> {
+        self.authorize_private(context)?;
+        Ok(self.email_verified_at.is_some())
+    }
+
+    async fn two_factor_enabled<'ctx>(&self, context: &Context<'ctx>) -> Result<bool, Error> {
+        self.authorize_private(context)?;
+        Ok(self.has_two_factor())
+    }
+
+    async fn access_level(&self) -> AccessLevel {
+        self.access_level
+    }
+
+    async fn suspended<'ctx>(&self, context: &Context<'ctx>) -> Result<bool, Error> {
+        self.authorize_private(context)?;
+        Ok(self.is_suspended())
+    }
+
+    async fn suspended_until<'ctx>(
+        &self,
+        context: &Context<'ctx>,
+    ) -> Result<Option<&DateTime<Utc>>, Error> {
+        self.authorize_private(context)?;
+        Ok(self.suspended_until.as_ref())
+    }
+
+    async fn suspension_reason<'ctx>(
+        &self,
+        context: &Context<'ctx>,
+    ) -> Result<Option<&str>, Error> {
+        self.authorize_private(context)?;
+        Ok(self.suspension_reason.as_deref())
     }
 
     async fn created_at(&self) -> &DateTime<Utc> {
@@ -224,6 +371,24 @@ impl User {
     }
 }
 
+impl User {
+    // Private fields are only returned if the requesting user is an admin
+    // or if user is the same as the user being queried
+    fn authorize_private(&self, context: &Context<'_>) -> Result<(), Error> {
+        if let Some(user) = context.data_opt::<Claims>() {
+            debug!("User:{:?}", user);
+            if user.access_level == AccessLevel::Admin
+                || Ulid::from_string(&user.ulid).unwrap() == self.id
+            {
+                return Ok(());
+            }
+        }
+
+        // return Not authorized error
+        Err(Error::new("Not authorized", StatusCode::UNAUTHORIZED))
+    }
+}
+
 impl sqlx::Type<sqlx::Postgres> for AccessLevel {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
         sqlx::postgres::PgTypeInfo::with_name("access_level")
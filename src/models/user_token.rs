@@ -0,0 +1,93 @@
+use sea_query::{Iden, Value};
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, Utc},
+    Decode, FromRow, Row,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq, Copy, Decode)]
+pub enum UserTokenKind {
+    /// Confirms that the user owns their email address.
+    EmailVerification,
+    /// Lets the user set a new password without knowing the old one.
+    PasswordReset,
+    /// Lets the user finish logging in with their second factor.
+    TwoFactorChallenge,
+}
+
+/// A single-use token mailed to a user.
+///
+/// Only a hash of the token is stored, the token itself only ever ends up in the mail.
+#[allow(dead_code)]
+#[derive(Clone, Debug)]
+pub struct UserToken {
+    pub id: i32,
+    pub user_id: String,
+    pub kind: UserTokenKind,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[allow(dead_code)]
+pub enum UserTokenIden {
+    Table,
+    Id,
+    UserId,
+    Kind,
+    TokenHash,
+    ExpiresAt,
+    UsedAt,
+    CreatedAt,
+}
+
+impl Iden for UserTokenIden {
+    fn unquoted(&self, s: &mut dyn std::fmt::Write) {
+        write!(
+            s,
+            "{}",
+            match self {
+                UserTokenIden::Table => "user_tokens",
+                UserTokenIden::Id => "id",
+                UserTokenIden::UserId => "user_id",
+                UserTokenIden::Kind => "kind",
+                UserTokenIden::TokenHash => "token_hash",
+                UserTokenIden::ExpiresAt => "expires_at",
+                UserTokenIden::UsedAt => "used_at",
+                UserTokenIden::CreatedAt => "created_at",
+            }
+        )
+        .unwrap();
+    }
+}
+
+impl<'r> FromRow<'r, PgRow> for UserToken {
+    fn from_row(row: &'r PgRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            kind: row.try_get("kind")?,
+            token_hash: row.try_get("token_hash")?,
+            expires_at: row.try_get("expires_at")?,
+            used_at: row.try_get("used_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+
+impl sqlx::Type<sqlx::Postgres> for UserTokenKind {
+    fn type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("user_token_kind")
+    }
+}
+
+impl From<UserTokenKind> for Value {
+    fn from(kind: UserTokenKind) -> Self {
+        match kind {
+            UserTokenKind::EmailVerification => "EmailVerification".into(),
+            UserTokenKind::PasswordReset => "PasswordReset".into(),
+            UserTokenKind::TwoFactorChallenge => "TwoFactorChallenge".into(),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use std::{error::Error, fs, path::PathBuf, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use sqlx::{migrate::Migrate as _, PgPool};
+use tokio::{sync::oneshot, time};
+use tracing::{info, warn};
+
+use super::{
+    config::{self, Config},
+    replicas::Replicas,
+    schema_diff, startup, telemetry,
+    token::generate_password,
+};
+use crate::{
+    constants::SCHEMA_SNAPSHOT_PATH,
+    database::{catalog, user},
+    models::user::AccessLevel,
+};
+
+type CommandResult = Result<(), Box<dyn Error>>;
+
+/// Unnamed weeb music database.
+///
+/// Starts the server when no command is given.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Config file, defaults to `UNK_DB_CONFIG` or ./config.toml
+    #[arg(long, short, global = true)]
+    pub config: Option<String>,
+    /// Override a config key, e.g. `--set db.url=postgres://...`
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the migrations, create an admin on the first run, then start the HTTP server
+    Serve,
+    /// Apply, undo or list the database migrations
+    Migrate {
+        #[command(subcommand)]
+        action: Migrate,
+    },
+    /// Create a user with the given role
+    CreateUser {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+        /// Generated and printed when left out
+        #[arg(long)]
+        password: Option<String>,
+        #[arg(long, value_enum, default_value_t = Role::User)]
+        role: Role,
+    },
+    /// Set a new password for a user and log them out everywhere
+    ResetPassword {
+        email: String,
+        /// Generated and printed when left out
+        #[arg(long)]
+        password: Option<String>,
+    },
+    /// Add the songs, artists, releases and tags of an export, skipping existing ones
+    Import { file: PathBuf },
+    /// Write the songs, artists, releases and tags as JSON
+    Export {
+        /// Written to stdout when left out
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Validate the config file and try to connect to the database
+    CheckConfig,
+    /// Print the GraphQL schema or compare it against the committed snapshot
+    Schema {
+        #[command(subcommand)]
+        action: Schema,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum Migrate {
+    /// Apply all pending migrations
+    Up,
+    /// Undo the latest migrations
+    Down {
+        #[arg(long, default_value_t = 1)]
+        steps: usize,
+    },
+    /// List the migrations and whether they're applied
+    Status,
+}
+
+#[derive(Subcommand)]
+pub enum Schema {
+    /// Print the SDL of the schema
+    Print,
+    /// Fail if the schema differs from the snapshot
+    Check {
+        #[arg(default_value = SCHEMA_SNAPSHOT_PATH)]
+        snapshot: String,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Role {
+    Admin,
+    Moderator,
+    Contributor,
+    User,
+}
+
+impl From<Role> for AccessLevel {
+    fn from(role: Role) -> Self {
+        match role {
+            Role::Admin => AccessLevel::Admin,
+            Role::Moderator => AccessLevel::Moderator,
+            Role::Contributor => AccessLevel::Contributor,
+            Role::User => AccessLevel::User,
+        }
+    }
+}
+
+/// Loads the config and runs the command, returning the exit code.
+pub async fn run(cli: Cli) -> i32 {
+    let conf = match config::load(cli.config.as_deref(), &cli.overrides) {
+        Ok(conf) => conf,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return 1;
+        }
+    };
+    if let Err(err) = telemetry::init(&conf.tracing) {
+        eprintln!("Error: {err}");
+        return 1;
+    }
+    match &conf.path {
+        Some(path) => info!("Loaded the config from {path}"),
+        None => info!("No config file found, using the defaults."),
+    }
+
+    let result = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => serve(conf).await,
+        Command::Migrate { action } => migrate(action, &conf).await,
+        Command::CreateUser {
+            username,
+            email,
+            password,
+            role,
+        } => create_user(username, email, password, role, &conf).await,
+        Command::ResetPassword { email, password } => reset_password(email, password, &conf).await,
+        Command::Import { file } => import(file, &conf).await,
+        Command::Export { output } => export(output, &conf).await,
+        Command::CheckConfig => check_config(&conf).await,
+        Command::Schema {
+            action: Schema::Print,
+        } => {
+            print!("{}", schema_diff::sdl());
+            Ok(())
+        }
+        Command::Schema {
+            action: Schema::Check { snapshot },
+        } => return schema_diff::check(&snapshot),
+    };
+
+    match result {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            1
+        }
+    }
+}
+
+async fn serve(conf: Config) -> CommandResult {
+    let pool = startup::connect(&conf).await?;
+    startup::MIGRATOR.run(&pool).await?;
+    startup::bootstrap_admin(&conf, &pool).await?;
+
+    let (stop, stopped) = oneshot::channel();
+    let drain_timeout = Duration::from_secs(conf.shutdown_timeout);
+    let replicas = Replicas::connect(&conf.db)?;
+    let (server, url) = startup::up(conf, pool.clone(), replicas.clone(), async {
+        stopped.await.ok();
+    })
+    .await?;
+    info!("App is running on: {url}");
+
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => result?,
+        _ = startup::shutdown_signal() => {
+            info!(
+                "Shutting down, waiting up to {}s for in-flight requests",
+                drain_timeout.as_secs()
+            );
+            stop.send(()).ok();
+            match time::timeout(drain_timeout, server).await {
+                Ok(result) => result?,
+                // Dropping the server drops the requests, rolling back their open transactions.
+                Err(_) => warn!("In-flight requests didn't finish in time, dropping them"),
+            }
+        }
+    }
+
+    pool.close().await;
+    replicas.close().await;
+    info!("Stopped");
+    Ok(())
+}
+
+async fn migrate(action: Migrate, conf: &Config) -> CommandResult {
+    let pool = startup::connect(conf).await?;
+
+    match action {
+        Migrate::Up => {
+            startup::MIGRATOR.run(&pool).await?;
+            println!("All migrations are applied.");
+        }
+        Migrate::Down { steps } => {
+            let applied = applied_versions(&pool).await?;
+            if applied.is_empty() {
+                println!("No migrations are applied.");
+                return Ok(());
+            }
+
+            // Undo everything newer than the version `steps` below the latest one.
+            let target = applied.iter().rev().nth(steps).copied().unwrap_or(0);
+            startup::MIGRATOR.undo(&pool, target).await?;
+            println!("Reverted to migration {target}.");
+        }
+        Migrate::Status => {
+            let applied = applied_versions(&pool).await?;
+            for migration in startup::MIGRATOR
+                .iter()
+                .filter(|migration| !migration.migration_type.is_down_migration())
+            {
+                let status = if applied.contains(&migration.version) {
+                    "applied"
+                } else {
+                    "pending"
+                };
+                println!(
+                    "{:<8} {} {}",
+                    status, migration.version, migration.description
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Versions of the applied migrations, oldest first, creating the table on a fresh database.
+async fn applied_versions(pool: &PgPool) -> Result<Vec<i64>, Box<dyn Error>> {
+    pool.acquire().await?.ensure_migrations_table().await?;
+    Ok(startup::applied_migrations(pool).await?)
+}
+
+async fn create_user(
+    username: String,
+    email: String,
+    password: Option<String>,
+    role: Role,
+    conf: &Config,
+) -> CommandResult {
+    let pool = startup::connect(conf).await?;
+    let password = password_or_generated(password);
+
+    let user = user::create_user(email, username, password, role.into(), &pool).await?;
+    println!("Created user {} ({}).", user.username, user.id);
+
+    Ok(())
+}
+
+async fn reset_password(email: String, password: Option<String>, conf: &Config) -> CommandResult {
+    let pool = startup::connect(conf).await?;
+    let options = crate::models::user::Options {
+        id: None,
+        email: Some(email),
+        page: None,
+        per_page: None,
+    };
+    let user = user::get_user(&options, &pool).await?;
+    let password = password_or_generated(password);
+
+    user::set_password(&user.id.to_string(), password, &pool).await?;
+    println!(
+        "Set a new password for {} and revoked their sessions.",
+        user.username
+    );
+
+    Ok(())
+}
+
+/// Generates a password and prints it if none was given.
+fn password_or_generated(password: Option<String>) -> String {
+    password.unwrap_or_else(|| {
+        let password = generate_password();
+        println!("Generated password: {password}");
+        password
+    })
+}
+
+async fn import(file: PathBuf, conf: &Config) -> CommandResult {
+    let export: serde_json::Value = serde_json::from_slice(&fs::read(&file)?)?;
+    let pool = startup::connect(conf).await?;
+
+    for (table, added) in catalog::import_catalog(&export, &pool).await? {
+        println!("{table}: {added} added");
+    }
+
+    Ok(())
+}
+
+async fn export(output: Option<PathBuf>, conf: &Config) -> CommandResult {
+    let pool = startup::connect(conf).await?;
+    let json = serde_json::to_string_pretty(&catalog::export_catalog(&pool).await?)?;
+
+    match output {
+        Some(path) => fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+
+    Ok(())
+}
+
+/// Loading the config already validated it, so only the database is left to check.
+async fn check_config(conf: &Config) -> CommandResult {
+    println!("The config is valid.");
+
+    startup::connect(conf).await?;
+    println!("Connected to the database.");
+
+    Ok(())
+}
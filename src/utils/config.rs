@@ -1,48 +1,523 @@
-use crate::constants;
-use core::panic;
+use crate::{constants, models::user::AccessLevel};
+use hyper::header;
 use serde::{Deserialize, Serialize};
-use tracing::info;
-use std::fs;
+use std::{fmt::Display, fs, io, net::IpAddr, path::Path};
+use toml::{value::Table, Value};
 
-pub fn get_config() -> Config {
-    fn check(path: String) -> String {
-        if let Err(error) = fs::read(&path) {
-            panic!("Failed to read the config file!! {:?}", error.to_string());
-        } else {
-            path
+/// Loads the config, each source overriding the ones before it:
+/// 1. the defaults,
+/// 2. the config file, `path` or `UNK_DB_CONFIG` if given, `./config.toml` if it exists,
+/// 3. `UNK_DB_*` environment variables, like `UNK_DB_PORT` or `UNK_DB_DB__URL` for `db.url`,
+/// 4. `overrides` from the command line, like `db.url=postgres://...`.
+///
+/// The config is loaded once and shared through the router data.
+pub fn load(path: Option<&str>, overrides: &[String]) -> Result<Config, ConfigError> {
+    let path = path
+        .map(str::to_string)
+        .or_else(|| super::get_env(constants::ENV_CONFIG_PATH))
+        .or_else(|| {
+            Path::new(constants::CONFIG_DEFAULT_PATH)
+                .exists()
+                .then(|| constants::CONFIG_DEFAULT_PATH.to_string())
+        });
+
+    let file = path.as_deref().map(read_file).transpose()?;
+    let mut config = from_sources(file, std::env::vars(), overrides)?;
+    config.path = path;
+
+    Ok(config)
+}
+
+fn read_file(path: &str) -> Result<Value, ConfigError> {
+    let contents = fs::read_to_string(path).map_err(|error| ConfigError::Read {
+        path: path.to_string(),
+        error,
+    })?;
+
+    toml::from_str(&contents).map_err(|error| ConfigError::Parse {
+        path: path.to_string(),
+        error,
+    })
+}
+
+fn from_sources(
+    file: Option<Value>,
+    env: impl Iterator<Item = (String, String)>,
+    overrides: &[String],
+) -> Result<Config, ConfigError> {
+    let mut config = Value::try_from(Config::default()).unwrap();
+
+    if let Some(file) = file {
+        merge(&mut config, file);
+    }
+
+    for (name, value) in env {
+        let Some(key) = name.strip_prefix(constants::ENV_CONFIG_PREFIX) else {
+            continue;
+        };
+        if name == constants::ENV_CONFIG_PATH {
+            continue;
         }
+
+        let key: Vec<String> = key.to_lowercase().split("__").map(str::to_string).collect();
+        set(&mut config, &key, &value);
     }
 
-    let path = super::get_env(constants::ENV_CONFIG_PATH)
-        .map(check)
-        .unwrap_or_else(|| constants::CONFIG_DEFAULT_PATH.to_string());
+    for item in overrides {
+        let Some((key, value)) = item.split_once('=') else {
+            return Err(ConfigError::Invalid {
+                key: item.clone(),
+                message: "overrides have to look like `key=value`".to_string(),
+            });
+        };
 
-    if let Ok(config) = confy::load_path::<Config>(path) {
-        config
-    } else {
-        info!("Failed to load the config file, falling back to default values.");
-        let conf = Config::default();
-        confy::store_path(constants::CONFIG_DEFAULT_PATH, &conf).unwrap();
-        conf
+        let key: Vec<String> = key.trim().split('.').map(str::to_string).collect();
+        set(&mut config, &key, value);
+    }
+
+    let config: Config = serde_path_to_error::deserialize(config).map_err(|error| {
+        // toml names the innermost table itself, the path is more useful.
+        let message = error.inner().to_string();
+        let message = message.split(" for key `").next().unwrap_or_default();
+        invalid(&error.path().to_string(), message)
+    })?;
+    config.validate()?;
+
+    Ok(config)
+}
+
+/// Merges the tables of `layer` into `base`, any other value replaces the one in `base`.
+fn merge(base: &mut Value, layer: Value) {
+    match (base, layer) {
+        (Value::Table(base), Value::Table(layer)) => {
+            for (key, value) in layer {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+}
+
+/// Sets the value at `key` from a string, typed like the value it replaces.
+fn set(config: &mut Value, key: &[String], raw: &str) {
+    let mut table = config.as_table_mut().unwrap();
+    for section in &key[..key.len() - 1] {
+        let entry = table
+            .entry(section.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        if !entry.is_table() {
+            *entry = Value::Table(Table::new());
+        }
+        table = entry.as_table_mut().unwrap();
+    }
+
+    let name = &key[key.len() - 1];
+    let value = match table.get(name) {
+        Some(Value::String(_)) => Some(Value::String(raw.to_string())),
+        Some(Value::Integer(_)) => raw.parse().map(Value::Integer).ok(),
+        Some(Value::Float(_)) => raw.parse().map(Value::Float).ok(),
+        Some(Value::Boolean(_)) => raw.parse().map(Value::Boolean).ok(),
+        // Arrays and anything without a default are written like in the config file.
+        _ => toml::from_str::<Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value")),
+    }
+    // Leaving unparsable values as strings gets them reported as the wrong type.
+    .unwrap_or_else(|| Value::String(raw.to_string()));
+
+    table.insert(name.clone(), value);
+}
+
+/// Why the config couldn't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read.
+    Read { path: String, error: io::Error },
+    /// The config file isn't valid TOML.
+    Parse {
+        path: String,
+        error: toml::de::Error,
+    },
+    /// A key is unknown, or its value has the wrong type or doesn't make sense.
+    Invalid { key: String, message: String },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read { path, error } => {
+                write!(f, "Failed to read the config file {path}: {error}")
+            }
+            Self::Parse { path, error } => {
+                write!(f, "Failed to parse the config file {path}: {error}")
+            }
+            Self::Invalid { key, message } => write!(f, "Invalid config key `{key}`: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn invalid(key: &str, message: &str) -> ConfigError {
+    ConfigError::Invalid {
+        key: key.to_string(),
+        message: message.to_string(),
     }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub name: String,
+    /// `production` refuses to start with the default auth key, admin password or database URL.
+    pub environment: Environment,
+    /// IPv4 or IPv6 address to listen on, `::` listens on both.
     pub ip: String,
     pub port: u16,
+    /// Listen on this Unix socket instead of `ip` and `port`, for reverse proxies.
+    pub unix_socket: String,
+    /// Serve HTTP/2 next to HTTP/1.1, negotiated through ALPN with TLS.
+    pub http2: bool,
+    /// Seconds in-flight requests get to finish on SIGINT or SIGTERM before they're dropped.
+    pub shutdown_timeout: u64,
     pub auth_key: String,
+    /// Password of the admin created on the first run, generated and printed once if empty.
     pub default_admin_password: String,
     pub default_admin_username: String,
     pub db: Db,
+    #[serde(default)]
+    pub mail: Mail,
+    #[serde(default)]
+    pub two_factor: TwoFactor,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    #[serde(default)]
+    pub query_limits: QueryLimits,
+    #[serde(default)]
+    pub persisted_queries: PersistedQueries,
+    #[serde(default)]
+    pub response_cache: ResponseCache,
+    pub tls: Tls,
+    pub cors: Cors,
+    #[serde(default)]
+    pub tracing: Tracing,
+    /// OpenID Connect providers users can log in with, as `[[oidc]]` tables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub oidc: Vec<OidcProvider>,
+    /// File the config was loaded from, `None` if it only came from the defaults and overrides.
+    #[serde(skip)]
+    pub path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct Db {
     pub max_connections: u32,
     pub connect_timeout: u64,
     pub url: String,
+    /// Read replicas GraphQL queries and REST reads are spread over, each with its own pool.
+    pub replica_urls: Vec<String>,
+    /// Seconds a client keeps reading from the primary after a mutation, so it sees its
+    /// changes while the replicas catch up.
+    pub primary_after_write: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Mail {
+    /// Where outgoing mail goes, `smtp`, `file` or `log`.
+    pub backend: MailBackend,
+    pub from: String,
+    /// Base URL of the frontend, the links in mails point here.
+    pub link_base_url: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    pub smtp_password: String,
+    /// Use STARTTLS, only disable this for local mail catchers.
+    pub smtp_starttls: bool,
+    /// Directory the `file` backend writes `.eml` files to.
+    pub file_dir: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TwoFactor {
+    /// Name authenticator apps show next to the codes.
+    pub issuer: String,
+    /// Access levels that have to use two-factor authentication, e.g. `["Admin", "Moderator"]`.
+    /// Users with these levels only get the permissions of a `User` until they enable it.
+    pub required_for: Vec<AccessLevel>,
+}
+
+impl Default for TwoFactor {
+    fn default() -> Self {
+        Self {
+            issuer: constants::TWO_FACTOR_DEFAULT_ISSUER.to_string(),
+            required_for: Vec::new(),
+        }
+    }
+}
+
+/// Budgets are counted per client over `window` seconds. Clients are told apart by their
+/// API key or user if they're authenticated, by their IP address otherwise. A budget of 0
+/// turns that limit off.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimit {
+    pub enabled: bool,
+    /// Length of a window in seconds.
+    pub window: u64,
+    /// Any request, whatever it does.
+    pub requests: u32,
+    /// GraphQL queries.
+    pub queries: u32,
+    /// GraphQL mutations and writes through the REST API.
+    pub mutations: u32,
+    /// Mutations that check credentials, like `login`, on top of the `mutations` budget.
+    pub logins: u32,
+    /// Failed logins in a row before the account is locked for the client.
+    pub lockout_threshold: u32,
+    /// Seconds of the first lockout, doubled with every further failure.
+    pub lockout_base: u64,
+    /// Longest lockout in seconds, failures are also forgotten after this long.
+    pub lockout_max: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window: constants::RATE_LIMIT_DEFAULT_WINDOW,
+            requests: constants::RATE_LIMIT_DEFAULT_REQUESTS,
+            queries: constants::RATE_LIMIT_DEFAULT_QUERIES,
+            mutations: constants::RATE_LIMIT_DEFAULT_MUTATIONS,
+            logins: constants::RATE_LIMIT_DEFAULT_LOGINS,
+            lockout_threshold: constants::RATE_LIMIT_DEFAULT_LOCKOUT_THRESHOLD,
+            lockout_base: constants::RATE_LIMIT_DEFAULT_LOCKOUT_BASE,
+            lockout_max: constants::RATE_LIMIT_DEFAULT_LOCKOUT_MAX,
+        }
+    }
+}
+
+/// How deep and expensive GraphQL queries may get, checked before they're executed.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct QueryLimits {
+    pub anonymous: QueryLimit,
+    /// Users that logged in.
+    pub authenticated: QueryLimit,
+    /// Requests authenticated with an API key.
+    pub api_key: QueryLimit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub struct QueryLimit {
+    /// How many levels fields may be nested.
+    pub depth: usize,
+    /// Sum of the costs of all fields. Fields that query the database cost more,
+    /// and lists multiply the cost of the fields inside them.
+    pub complexity: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            anonymous: QueryLimit {
+                depth: constants::QUERY_DEFAULT_MAX_DEPTH,
+                complexity: constants::QUERY_DEFAULT_MAX_COMPLEXITY,
+            },
+            authenticated: QueryLimit {
+                depth: constants::QUERY_DEFAULT_AUTHENTICATED_MAX_DEPTH,
+                complexity: constants::QUERY_DEFAULT_AUTHENTICATED_MAX_COMPLEXITY,
+            },
+            api_key: QueryLimit {
+                depth: constants::QUERY_DEFAULT_API_KEY_MAX_DEPTH,
+                complexity: constants::QUERY_DEFAULT_API_KEY_MAX_COMPLEXITY,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PersistedQueries {
+    /// Accept automatic persisted queries: requests that only send the hash of a query,
+    /// and unknown queries sent along with their hash to register them.
+    pub automatic: bool,
+    /// Only run queries an admin registered with `registerPersistedQuery`, admins are exempt.
+    /// Clients can still send only the hash of these, but can't register new ones.
+    pub allow_list: bool,
+}
+
+impl Default for PersistedQueries {
+    fn default() -> Self {
+        Self {
+            automatic: true,
+            allow_list: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ResponseCache {
+    /// Keep responses to anonymous queries in memory until a write could have changed them.
+    pub enabled: bool,
+    /// Seconds a response is kept at most. Clients and proxies may reuse responses to
+    /// anonymous GET requests for as long.
+    pub max_age: u64,
+    /// Responses kept at most, once full new ones are only kept after old ones expire.
+    pub max_entries: usize,
+}
+
+impl Default for ResponseCache {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_age: constants::RESPONSE_CACHE_DEFAULT_MAX_AGE,
+            max_entries: constants::RESPONSE_CACHE_DEFAULT_MAX_ENTRIES,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Tls {
+    /// PEM file with the certificate chain, HTTPS is served when it's set.
+    /// Changes to the files are picked up without a restart.
+    pub cert_path: String,
+    /// PEM file with the private key of the certificate.
+    pub key_path: String,
+}
+
+impl Tls {
+    pub fn enabled(&self) -> bool {
+        !self.cert_path.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Cors {
+    /// Origins browsers may call the API from, like `https://example.com`, or `*` for any.
+    pub allowed_origins: Vec<String>,
+    /// Let browsers send cookies and `Authorization` headers along. The matching origin is
+    /// sent back instead of `*`, so `allowed_origins` has to list them.
+    pub allow_credentials: bool,
+    /// Seconds browsers may cache the answer to a preflight request.
+    pub max_age: u64,
+    /// Response headers scripts may read.
+    pub exposed_headers: Vec<String>,
+}
+
+impl Cors {
+    /// The value of `Access-Control-Allow-Origin` for a request from `origin`, `None` if the
+    /// origin isn't allowed.
+    pub fn allow_origin<'a>(&self, origin: Option<&'a str>) -> Option<&'a str> {
+        if !self.allow_credentials && self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            return Some("*");
+        }
+
+        origin.filter(|origin| self.allowed_origins.iter().any(|allowed| allowed == origin))
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allowed_origins: constants::CORS_DEFAULT_ALLOWED_ORIGINS
+                .map(String::from)
+                .to_vec(),
+            allow_credentials: false,
+            max_age: constants::CORS_DEFAULT_MAX_AGE,
+            exposed_headers: constants::CORS_DEFAULT_EXPOSED_HEADERS
+                .map(String::from)
+                .to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Tracing {
+    /// OTLP gRPC endpoint traces are exported to, e.g. `http://localhost:4317`.
+    /// Traces aren't exported while it's empty.
+    pub otlp_endpoint: String,
+    /// Name the server shows up as in traces.
+    pub service_name: String,
+    /// Share of traces to export, from 0 to 1. Requests that come with a trace context
+    /// follow the sampling decision of the caller.
+    pub sample_ratio: f64,
+}
+
+impl Default for Tracing {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: String::new(),
+            service_name: constants::APP_NAME.to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OidcProvider {
+    /// Identifies the provider in the API, e.g. `github`.
+    pub name: String,
+    /// Issuer URL, the discovery document is fetched from `{issuer}/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Page of the frontend the provider redirects back to, has to be registered with the provider.
+    pub redirect_uri: String,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    constants::OIDC_DEFAULT_SCOPES
+        .iter()
+        .map(|scope| scope.to_string())
+        .collect()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Environment {
+    Development,
+    Production,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailBackend {
+    Smtp,
+    File,
+    Log,
+}
+
+impl Default for Mail {
+    fn default() -> Self {
+        Self {
+            backend: MailBackend::Log,
+            from: constants::MAIL_DEFAULT_FROM.to_string(),
+            link_base_url: constants::MAIL_DEFAULT_LINK_BASE_URL.to_string(),
+            smtp_host: String::new(),
+            smtp_port: constants::MAIL_DEFAULT_SMTP_PORT,
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            smtp_starttls: true,
+            file_dir: constants::MAIL_DEFAULT_FILE_DIR.to_string(),
+        }
+    }
 }
 
 impl Default for Db {
@@ -51,6 +526,8 @@ impl Default for Db {
             max_connections: constants::DB_DEFAULT_MAX_CONNECTIONS,
             connect_timeout: constants::DB_DEFAULT_CONNECT_TIMEOUT,
             url: constants::DB_DEFAULT_URL.to_string(),
+            replica_urls: Vec::new(),
+            primary_after_write: constants::DB_DEFAULT_PRIMARY_AFTER_WRITE,
         }
     }
 }
@@ -59,12 +536,290 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             name: String::new(),
+            environment: Environment::Development,
             ip: constants::SERVER_DEFAULT_IP.to_string(),
             port: constants::SERVER_DEFAULT_PORT,
+            unix_socket: String::new(),
+            http2: true,
+            shutdown_timeout: constants::SERVER_DEFAULT_SHUTDOWN_TIMEOUT,
             db: Db::default(),
+            mail: Mail::default(),
+            two_factor: TwoFactor::default(),
+            rate_limit: RateLimit::default(),
+            query_limits: QueryLimits::default(),
+            persisted_queries: PersistedQueries::default(),
+            response_cache: ResponseCache::default(),
+            tls: Tls::default(),
+            cors: Cors::default(),
+            tracing: Tracing::default(),
+            oidc: Vec::new(),
+            path: None,
             auth_key: constants::AUTH_DEFAULT_KEY.to_string(),
-            default_admin_password: constants::ADMIN_DEFAULT_PASSWORD.to_string(),
+            default_admin_password: String::new(),
             default_admin_username: constants::ADMIN_DEFAULT_USERNAME.to_string(),
         }
     }
 }
+
+impl Config {
+    /// Checks the values that deserialize fine but can't work.
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.ip.parse::<IpAddr>().is_err() {
+            return Err(invalid("ip", "has to be an IPv4 or IPv6 address"));
+        }
+        if self.tls.cert_path.is_empty() != self.tls.key_path.is_empty() {
+            let key = if self.tls.key_path.is_empty() {
+                "tls.key_path"
+            } else {
+                "tls.cert_path"
+            };
+            return Err(invalid(key, "is required when the other TLS file is set"));
+        }
+        // Checked once here, so signing and verifying tokens can't fail on it later.
+        if jsonwebtoken::DecodingKey::from_base64_secret(&self.auth_key).is_err() {
+            return Err(invalid("auth_key", "has to be base64"));
+        }
+        if self.environment == Environment::Production {
+            if self.auth_key == constants::AUTH_DEFAULT_KEY {
+                return Err(invalid("auth_key", "can't be the default in production"));
+            }
+            if self.default_admin_password == constants::ADMIN_DEFAULT_PASSWORD {
+                return Err(invalid(
+                    "default_admin_password",
+                    "can't be the default in production, leave it empty to generate one",
+                ));
+            }
+            if self.db.url == constants::DB_DEFAULT_URL {
+                return Err(invalid("db.url", "can't be the default in production"));
+            }
+        }
+        if self.default_admin_username.is_empty() {
+            return Err(invalid("default_admin_username", "can't be empty"));
+        }
+        if !is_postgres_url(&self.db.url) {
+            return Err(invalid("db.url", "has to be a postgres:// URL"));
+        }
+        for (i, url) in self.db.replica_urls.iter().enumerate() {
+            if !is_postgres_url(url) {
+                return Err(invalid(
+                    &format!("db.replica_urls[{i}]"),
+                    "has to be a postgres:// URL",
+                ));
+            }
+        }
+        if self.db.max_connections == 0 {
+            return Err(invalid("db.max_connections", "has to be at least 1"));
+        }
+        if self.mail.backend == MailBackend::Smtp && self.mail.smtp_host.is_empty() {
+            return Err(invalid("mail.smtp_host", "is required by the smtp backend"));
+        }
+        for (i, origin) in self.cors.allowed_origins.iter().enumerate() {
+            if origin == "*" {
+                if self.cors.allow_credentials {
+                    return Err(invalid(
+                        &format!("cors.allowed_origins[{i}]"),
+                        "can't be `*` while allow_credentials is on, list the origins instead",
+                    ));
+                }
+            } else if !is_origin(origin) {
+                return Err(invalid(
+                    &format!("cors.allowed_origins[{i}]"),
+                    "has to be `*` or an origin like https://example.com",
+                ));
+            }
+        }
+        for (i, name) in self.cors.exposed_headers.iter().enumerate() {
+            if header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(invalid(
+                    &format!("cors.exposed_headers[{i}]"),
+                    "isn't a valid header name",
+                ));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.tracing.sample_ratio) {
+            return Err(invalid("tracing.sample_ratio", "has to be between 0 and 1"));
+        }
+        if self.rate_limit.enabled && self.rate_limit.window == 0 {
+            return Err(invalid("rate_limit.window", "has to be at least 1"));
+        }
+        for (i, provider) in self.oidc.iter().enumerate() {
+            if self.oidc[..i]
+                .iter()
+                .any(|other| other.name == provider.name)
+            {
+                return Err(invalid(
+                    &format!("oidc[{i}].name"),
+                    "is used by another provider",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// Origins are a scheme and host, optionally with a port, but no path.
+fn is_origin(origin: &str) -> bool {
+    match origin.split_once("://") {
+        Some((scheme, host)) => {
+            matches!(scheme, "http" | "https")
+                && !host.is_empty()
+                && !host.contains(['/', '?', '#'])
+                && header::HeaderValue::from_str(origin).is_ok()
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(file: &str, env: &[(&str, &str)], overrides: &[&str]) -> Result<Config, ConfigError> {
+        let env = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let overrides = overrides
+            .iter()
+            .map(|item| item.to_string())
+            .collect::<Vec<_>>();
+
+        from_sources(
+            Some(toml::from_str(file).unwrap()),
+            env.into_iter(),
+            &overrides,
+        )
+    }
+
+    fn invalid_key(result: Result<Config, ConfigError>) -> String {
+        match result {
+            Err(ConfigError::Invalid { key, .. }) => key,
+            other => panic!("expected an invalid key, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_layers() {
+        let file = r#"
+            port = 7000
+            [db]
+            url = "postgres://file/db"
+            [rate_limit]
+            requests = 10
+        "#;
+        let env = [
+            ("UNK_DB_DB__URL", "postgres://env/db"),
+            ("UNK_DB_RATE_LIMIT__ENABLED", "false"),
+            ("UNK_DB_CONFIG", "ignored.toml"),
+            ("HOME", "/root"),
+        ];
+        let config = load(
+            file,
+            &env,
+            &["port=8000", "two_factor.required_for=[\"Admin\"]"],
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 8000);
+        assert_eq!(config.db.url, "postgres://env/db");
+        assert_eq!(
+            config.db.max_connections,
+            constants::DB_DEFAULT_MAX_CONNECTIONS
+        );
+        assert!(!config.rate_limit.enabled);
+        assert_eq!(config.rate_limit.requests, 10);
+        assert_eq!(
+            config.rate_limit.window,
+            constants::RATE_LIMIT_DEFAULT_WINDOW
+        );
+        assert_eq!(config.two_factor.required_for, [AccessLevel::Admin]);
+    }
+
+    #[test]
+    fn test_errors_name_the_key() {
+        assert_eq!(invalid_key(load("port = \"abc\"", &[], &[])), "port");
+        assert_eq!(
+            invalid_key(load("", &[("UNK_DB_DB__MAX_CONNECTIONS", "many")], &[])),
+            "db.max_connections"
+        );
+        assert_eq!(
+            invalid_key(load("[mail]\nbackend = \"pigeon\"", &[], &[])),
+            "mail.backend"
+        );
+        assert_eq!(
+            invalid_key(load("[db]\nulr = \"postgres://\"", &[], &[])),
+            "db.ulr"
+        );
+        assert_eq!(invalid_key(load("", &[], &["db.url=mysql://db"])), "db.url");
+        assert_eq!(invalid_key(load("", &[], &["port"])), "port");
+        assert_eq!(
+            invalid_key(load("", &[], &["auth_key=not base64!"])),
+            "auth_key"
+        );
+    }
+
+    #[test]
+    fn test_production_refuses_defaults() {
+        let secrets = [
+            "auth_key=c3VwZXJzZWNyZXQ=",
+            "db.url=postgres://weeb:hunter2@db:5432/weeb",
+        ];
+        assert!(load("", &[], &secrets).is_ok());
+        assert!(load("environment = \"production\"", &[], &secrets).is_ok());
+
+        let production = [("UNK_DB_ENVIRONMENT", "production")];
+        assert_eq!(invalid_key(load("", &production, &[])), "auth_key");
+        assert_eq!(invalid_key(load("", &production, &secrets[..1])), "db.url");
+        assert_eq!(
+            invalid_key(load(
+                "default_admin_password = \"admin\"",
+                &production,
+                &secrets
+            )),
+            "default_admin_password"
+        );
+    }
+
+    #[test]
+    fn test_cors() {
+        let cors = Cors::default();
+        assert_eq!(cors.allow_origin(Some("https://example.com")), Some("*"));
+        assert_eq!(cors.allow_origin(None), Some("*"));
+
+        let cors = Cors {
+            allowed_origins: vec!["https://example.com".to_string()],
+            allow_credentials: true,
+            ..Cors::default()
+        };
+        assert_eq!(
+            cors.allow_origin(Some("https://example.com")),
+            Some("https://example.com")
+        );
+        assert_eq!(cors.allow_origin(Some("https://evil.example.com")), None);
+        assert_eq!(cors.allow_origin(None), None);
+
+        assert_eq!(
+            invalid_key(load("[cors]\nallow_credentials = true", &[], &[])),
+            "cors.allowed_origins[0]"
+        );
+        assert_eq!(
+            invalid_key(load(
+                "[cors]\nallowed_origins = [\"https://example.com/\"]",
+                &[],
+                &[]
+            )),
+            "cors.allowed_origins[0]"
+        );
+        assert!(load(
+            "[cors]\nallowed_origins = [\"http://localhost:3000\"]",
+            &[],
+            &[]
+        )
+        .is_ok());
+    }
+}
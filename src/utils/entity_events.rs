@@ -0,0 +1,68 @@
+use std::{sync::Arc, time::Duration};
+
+use futures::{stream, Stream};
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, warn};
+
+use crate::{
+    constants::{ENTITY_CHANGES_BUFFER, ENTITY_CHANGES_CHANNEL, ENTITY_CHANGES_RETRY_DELAY},
+    models::entity_change::EntityChange,
+};
+
+/// Hands out the changes Postgres notifies about, whichever server instance made them.
+pub struct EntityEvents {
+    sender: broadcast::Sender<EntityChange>,
+}
+
+impl EntityEvents {
+    /// Starts listening for changes in the background.
+    pub async fn listen(db: &PgPool) -> Result<Arc<Self>, sqlx::Error> {
+        let mut listener = PgListener::connect_with(db).await?;
+        listener.listen(ENTITY_CHANGES_CHANNEL).await?;
+
+        let (sender, _) = broadcast::channel(ENTITY_CHANGES_BUFFER);
+        let events = Arc::new(Self {
+            sender: sender.clone(),
+        });
+
+        let mut closed = db.close_event();
+        tokio::spawn(async move {
+            // Stops when the pool closes on shutdown, so closing it doesn't wait for the listener.
+            while let Ok(result) = closed.do_until(listener.recv()).await {
+                match result {
+                    Ok(notification) => {
+                        match serde_json::from_str::<EntityChange>(notification.payload()) {
+                            // Failing only means nobody is subscribed right now.
+                            Ok(change) => drop(sender.send(change)),
+                            Err(err) => error!("Invalid entity change notification: {err}"),
+                        }
+                    }
+                    Err(sqlx::Error::PoolClosed) => break,
+                    // The listener reconnects on the next call, changes in between are lost.
+                    Err(err) => {
+                        error!("Lost the connection listening for entity changes: {err}");
+                        tokio::time::sleep(Duration::from_millis(ENTITY_CHANGES_RETRY_DELAY)).await;
+                    }
+                }
+            }
+        });
+
+        Ok(events)
+    }
+
+    /// Changes from now on, subscribers that fall too far behind skip the oldest ones.
+    pub fn subscribe(&self) -> impl Stream<Item = EntityChange> {
+        stream::unfold(self.sender.subscribe(), |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(change) => return Some((change, receiver)),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Subscriber fell behind, skipped {skipped} entity changes")
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+}
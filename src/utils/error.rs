@@ -4,6 +4,7 @@ use std::{
     fmt::Display,
     io::{self, ErrorKind},
 };
+use utoipa::ToSchema;
 
 #[derive(Debug, Clone)]
 pub struct Error {
@@ -11,7 +12,7 @@ pub struct Error {
     pub message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ErrorResponse {
     status_code: u16,
     message: String,
@@ -76,6 +77,39 @@ impl From<sqlx::Error> for Error {
     }
 }
 
+impl From<lettre::address::AddressError> for Error {
+    fn from(_: lettre::address::AddressError) -> Self {
+        Self::new("INVALID_EMAIL", StatusCode::BAD_REQUEST)
+    }
+}
+
+impl From<lettre::error::Error> for Error {
+    fn from(_: lettre::error::Error) -> Self {
+        Self::new(
+            "Could not build the mail!",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for Error {
+    fn from(_: lettre::transport::smtp::Error) -> Self {
+        Self::new(
+            "Could not send the mail!",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    }
+}
+
+impl From<lettre::transport::file::Error> for Error {
+    fn from(_: lettre::transport::file::Error) -> Self {
+        Self::new(
+            "Could not send the mail!",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        )
+    }
+}
+
 impl From<jsonwebtoken::errors::Error> for Error {
     fn from(error: jsonwebtoken::errors::Error) -> Self {
         match error.into_kind() {
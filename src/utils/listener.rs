@@ -0,0 +1,208 @@
+use std::{
+    fmt::Display,
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use futures::stream;
+use hyper::server::accept::{self, Accept};
+use socket2::{Domain, Socket, Type};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time,
+};
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, warn};
+
+use super::{config::Config, tls};
+use crate::constants::TLS_HANDSHAKE_TIMEOUT;
+
+/// A connection the server accepted, over TCP or a Unix socket, possibly wrapped in TLS.
+pub trait Connection: AsyncRead + AsyncWrite + Send + Unpin + 'static {
+    /// Address of the client. Unix sockets don't have one, it's unspecified for them.
+    fn remote_addr(&self) -> SocketAddr;
+}
+
+impl Connection for TcpStream {
+    fn remote_addr(&self) -> SocketAddr {
+        match self.peer_addr() {
+            // Clients on IPv4 show up as mapped IPv6 addresses on dual-stack sockets.
+            Ok(addr) => SocketAddr::new(addr.ip().to_canonical(), addr.port()),
+            Err(_) => unspecified(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Connection for tokio::net::UnixStream {
+    fn remote_addr(&self) -> SocketAddr {
+        unspecified()
+    }
+}
+
+impl Connection for Box<dyn Connection> {
+    fn remote_addr(&self) -> SocketAddr {
+        (**self).remote_addr()
+    }
+}
+
+impl Connection for TlsStream<Box<dyn Connection>> {
+    fn remote_addr(&self) -> SocketAddr {
+        self.get_ref().0.remote_addr()
+    }
+}
+
+fn unspecified() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0)
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, std::path::PathBuf),
+}
+
+impl Listener {
+    /// Binds the Unix socket if one is configured, `ip` and `port` otherwise.
+    pub fn bind(conf: &Config) -> io::Result<Self> {
+        if !conf.unix_socket.is_empty() {
+            return Self::bind_unix(&conf.unix_socket);
+        }
+
+        let addr = SocketAddr::new(conf.ip.parse().unwrap(), conf.port);
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        if addr.is_ipv6() && addr.ip().is_unspecified() {
+            // Accept IPv4 as well, whatever the system default is.
+            socket.set_only_v6(false)?;
+        }
+        socket.set_reuse_address(true)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        socket.listen(1024)?;
+
+        Ok(Self::Tcp(TcpListener::from_std(socket.into())?))
+    }
+
+    #[cfg(unix)]
+    fn bind_unix(path: &str) -> io::Result<Self> {
+        use std::os::unix::fs::FileTypeExt;
+
+        // A socket left behind by an earlier run that didn't stop cleanly.
+        if std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+
+        Ok(Self::Unix(
+            tokio::net::UnixListener::bind(path)?,
+            path.into(),
+        ))
+    }
+
+    #[cfg(not(unix))]
+    fn bind_unix(_path: &str) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix sockets aren't supported on this platform",
+        ))
+    }
+
+    /// Where the server can be reached, for the logs.
+    pub fn url(&self, tls: bool) -> impl Display {
+        let scheme = if tls { "https" } else { "http" };
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => format!("{scheme}://{addr}"),
+                Err(_) => format!("{scheme}://"),
+            },
+            #[cfg(unix)]
+            Self::Unix(_, path) => format!("{scheme}+unix://{}", path.display()),
+        }
+    }
+
+    async fn accept(&self) -> io::Result<Box<dyn Connection>> {
+        match self {
+            Self::Tcp(listener) => {
+                let (stream, _) = listener.accept().await?;
+                stream.set_nodelay(true)?;
+                Ok(Box::new(stream))
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, _) => Ok(Box::new(listener.accept().await?.0)),
+        }
+    }
+
+    /// Accepts connections in the background, doing the TLS handshake first if it's enabled.
+    /// Stops once the server drops the returned connections.
+    pub fn incoming(
+        self,
+        conf: &Config,
+    ) -> io::Result<impl Accept<Conn = Box<dyn Connection>, Error = io::Error>> {
+        let acceptor = match conf.tls.enabled() {
+            true => Some(tls::acceptor(&conf.tls, conf.http2)?),
+            false => None,
+        };
+        let (sender, receiver) = mpsc::channel(64);
+
+        tokio::spawn(async move {
+            loop {
+                let conn = tokio::select! {
+                    conn = self.accept() => conn,
+                    _ = sender.closed() => return,
+                };
+                let conn = match conn {
+                    Ok(conn) => conn,
+                    // Usually out of file descriptors, give connections some time to close.
+                    Err(err) => {
+                        warn!("Failed to accept a connection: {err}");
+                        time::sleep(Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                match &acceptor {
+                    Some(acceptor) => {
+                        // Handshakes run on their own, so slow clients don't hold up others.
+                        let handshake = acceptor.accept(conn);
+                        let sender = sender.clone();
+                        tokio::spawn(async move {
+                            let timeout = Duration::from_secs(TLS_HANDSHAKE_TIMEOUT);
+                            match time::timeout(timeout, handshake).await {
+                                Ok(Ok(stream)) => {
+                                    let conn: Box<dyn Connection> = Box::new(stream);
+                                    sender.send(conn).await.ok();
+                                }
+                                Ok(Err(err)) => debug!("TLS handshake failed: {err}"),
+                                Err(_) => debug!("TLS handshake timed out"),
+                            }
+                        });
+                    }
+                    None => {
+                        if sender.send(conn).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+
+        Ok(accept::from_stream(stream::unfold(
+            receiver,
+            |mut receiver| async move {
+                let conn = receiver.recv().await?;
+                Some((Ok(conn), receiver))
+            },
+        )))
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Self::Unix(_, path) = self {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use lettre::{
+    message::Mailbox, transport::smtp::authentication::Credentials, AsyncFileTransport,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use tracing::info;
+
+use super::{
+    config::{self, MailBackend},
+    error::Error,
+};
+
+/// Sends mail to users through the backend picked in the config.
+pub struct Mailer {
+    from: Mailbox,
+    link_base_url: String,
+    transport: Transport,
+}
+
+enum Transport {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    /// Writes every mail as an `.eml` file, meant for local development and tests.
+    File(AsyncFileTransport<Tokio1Executor>),
+    /// Only logs the mail, nothing gets sent.
+    Log,
+}
+
+impl Mailer {
+    pub fn from_config(conf: &config::Mail) -> Result<Self, Error> {
+        let transport = match conf.backend {
+            MailBackend::Smtp => {
+                let builder = if conf.smtp_starttls {
+                    AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&conf.smtp_host)?
+                } else {
+                    AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&conf.smtp_host)
+                };
+
+                let builder = if conf.smtp_username.is_empty() {
+                    builder
+                } else {
+                    builder.credentials(Credentials::new(
+                        conf.smtp_username.clone(),
+                        conf.smtp_password.clone(),
+                    ))
+                };
+
+                Transport::Smtp(builder.port(conf.smtp_port).build())
+            }
+            MailBackend::File => {
+                std::fs::create_dir_all(&conf.file_dir)?;
+                Transport::File(AsyncFileTransport::new(&conf.file_dir))
+            }
+            MailBackend::Log => Transport::Log,
+        };
+
+        Ok(Self {
+            from: conf.from.parse()?,
+            link_base_url: conf.link_base_url.trim_end_matches('/').to_string(),
+            transport,
+        })
+    }
+
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), Error> {
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .body(body)?;
+
+        match &self.transport {
+            Transport::Smtp(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::File(transport) => {
+                transport.send(message).await?;
+            }
+            Transport::Log => {
+                let formatted = String::from_utf8_lossy(&message.formatted()).to_string();
+                info!("Not sending mail, the log backend is used:\n{formatted}");
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn send_email_verification(&self, to: &str, token: &str) -> Result<(), Error> {
+        let link = format!("{}/verify-email?token={token}", self.link_base_url);
+        let body = format!(
+            "Please confirm your email address by opening the link below.\n\n\
+             {link}\n\n\
+             If you didn't create an account, you can ignore this mail.\n"
+        );
+
+        self.send(to, "Verify your email address", body).await
+    }
+
+    pub async fn send_password_reset(&self, to: &str, token: &str) -> Result<(), Error> {
+        let link = format!("{}/reset-password?token={token}", self.link_base_url);
+        let body = format!(
+            "Someone asked to reset the password of your account.\n\
+             Open the link below to pick a new one, it stays valid for an hour.\n\n\
+             {link}\n\n\
+             If this wasn't you, you can ignore this mail.\n"
+        );
+
+        self.send(to, "Reset your password", body).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_file_backend() {
+        let dir = std::env::temp_dir().join(format!("mailer-{}", ulid::Ulid::new()));
+        let conf = config::Mail {
+            backend: MailBackend::File,
+            file_dir: dir.to_string_lossy().to_string(),
+            link_base_url: "http://localhost:3000/".to_string(),
+            ..config::Mail::default()
+        };
+
+        let mailer = Mailer::from_config(&conf).unwrap();
+        mailer
+            .send_password_reset("user@example.com", "abc")
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let mail = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(mail.contains("To: user@example.com"));
+        assert!(mail.contains("http://localhost:3000/reset-password?token=abc"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
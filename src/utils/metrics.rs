@@ -0,0 +1,272 @@
+use std::{sync::Arc, time::Duration};
+
+use async_graphql::parser::types::OperationType;
+use prometheus::{
+    histogram_opts, opts, Encoder, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Registry,
+    TextEncoder,
+};
+use sqlx::PgPool;
+
+/// Routes requests are counted by, so ids in paths and unknown paths don't turn into labels.
+const ROUTES: [&str; 17] = [
+    "/graphql",
+    "/graphiql",
+    "/healthz",
+    "/readyz",
+    "/version",
+    "/metrics",
+    "/api/v1/songs",
+    "/api/v1/songs/:id",
+    "/api/v1/songs/:id/artists",
+    "/api/v1/songs/:id/releases",
+    "/api/v1/songs/:id/tags",
+    "/api/v1/artists",
+    "/api/v1/artists/:id",
+    "/api/v1/releases",
+    "/api/v1/tags",
+    "/api/v1/openapi.json",
+    "/api/v1/docs",
+];
+
+/// Prometheus metrics of the server, rendered by `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    graphql_operations: IntCounterVec,
+    graphql_duration: HistogramVec,
+    graphql_errors: IntCounterVec,
+    auth_events: IntCounterVec,
+    db_connections: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new(max_connections: u32) -> Arc<Self> {
+        let registry = Registry::new_custom(Some("unk_db".to_string()), None).unwrap();
+
+        let http_requests = IntCounterVec::new(
+            opts!("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_duration = HistogramVec::new(
+            histogram_opts!(
+                "http_request_duration_seconds",
+                "Time to respond to HTTP requests"
+            ),
+            &["method", "route"],
+        )
+        .unwrap();
+        let graphql_operations = IntCounterVec::new(
+            opts!(
+                "graphql_operations_total",
+                "GraphQL operations by name and outcome"
+            ),
+            &["type", "operation", "outcome"],
+        )
+        .unwrap();
+        let graphql_duration = HistogramVec::new(
+            histogram_opts!(
+                "graphql_operation_duration_seconds",
+                "Time to execute GraphQL operations"
+            ),
+            &["type", "operation"],
+        )
+        .unwrap();
+        let graphql_errors = IntCounterVec::new(
+            opts!(
+                "graphql_resolver_errors_total",
+                "Errors returned by resolvers, by code"
+            ),
+            &["code"],
+        )
+        .unwrap();
+        let auth_events = IntCounterVec::new(
+            opts!("auth_events_total", "Logins and token refreshes by outcome"),
+            &["event", "outcome"],
+        )
+        .unwrap();
+        let db_connections = IntGaugeVec::new(
+            opts!("db_pool_connections", "Open database connections by state"),
+            &["state"],
+        )
+        .unwrap();
+        let db_max_connections = IntGauge::new(
+            "db_pool_max_connections",
+            "Connections the database pool opens at most",
+        )
+        .unwrap();
+        db_max_connections.set(max_connections.into());
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_duration.clone())).unwrap();
+        registry
+            .register(Box::new(graphql_operations.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(graphql_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(graphql_errors.clone())).unwrap();
+        registry.register(Box::new(auth_events.clone())).unwrap();
+        registry.register(Box::new(db_connections.clone())).unwrap();
+        registry.register(Box::new(db_max_connections)).unwrap();
+
+        Arc::new(Self {
+            registry,
+            http_requests,
+            http_duration,
+            graphql_operations,
+            graphql_duration,
+            graphql_errors,
+            auth_events,
+            db_connections,
+        })
+    }
+
+    pub fn record_http(&self, method: &str, path: &str, status: u16, elapsed: Duration) {
+        let route = route(path);
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Counts an executed operation, and the errors its resolvers returned.
+    ///
+    /// # Arguments
+    /// * `operation` - Type and name of the operation, `None` if the document couldn't be parsed.
+    ///   Operations without a name that can be trusted are counted as `other`.
+    pub fn record_graphql(
+        &self,
+        operation: Option<(OperationType, Option<&str>)>,
+        response: &async_graphql::Response,
+        elapsed: Duration,
+    ) {
+        let (ty, name) = match operation {
+            Some((ty, name)) => (ty.to_string(), name.unwrap_or("other")),
+            None => ("unknown".to_string(), "other"),
+        };
+        let outcome = if response.errors.is_empty() {
+            "ok"
+        } else {
+            "error"
+        };
+
+        self.graphql_operations
+            .with_label_values(&[&ty, name, outcome])
+            .inc();
+        self.graphql_duration
+            .with_label_values(&[&ty, name])
+            .observe(elapsed.as_secs_f64());
+
+        // Errors without a path didn't come from a resolver, but from parsing or validation.
+        for error in response
+            .errors
+            .iter()
+            .filter(|error| !error.path.is_empty())
+        {
+            self.graphql_errors
+                .with_label_values(&[error_code(&error.message)])
+                .inc();
+        }
+    }
+
+    /// Counts a login or token refresh.
+    ///
+    /// # Arguments
+    /// * `event` - `login`, `oidc_login`, `two_factor` or `refresh_token`.
+    pub fn record_auth(&self, event: &str, succeeded: bool) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.auth_events.with_label_values(&[event, outcome]).inc();
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self, pool: &PgPool) -> String {
+        let idle = pool.num_idle() as i64;
+        self.db_connections.with_label_values(&["idle"]).set(idle);
+        self.db_connections
+            .with_label_values(&["in_use"])
+            .set(i64::from(pool.size()) - idle);
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// The route the path belongs to, `other` if it's none of them.
+pub fn route(path: &str) -> &'static str {
+    let segments: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+
+    ROUTES
+        .iter()
+        .find(|route| {
+            let route: Vec<&str> = route.trim_end_matches('/').split('/').collect();
+            route.len() == segments.len()
+                && route
+                    .iter()
+                    .zip(&segments)
+                    .all(|(route, segment)| route.starts_with(':') || route == segment)
+        })
+        .copied()
+        .unwrap_or("other")
+}
+
+/// Error messages are codes, some with details after a colon, followed by the status code
+/// when they're converted from our [`Error`](super::error::Error). Anything that doesn't look
+/// like a code is counted as `other`, so messages can't add series.
+fn error_code(message: &str) -> &str {
+    let message = message
+        .split(" occurred with status code")
+        .next()
+        .unwrap_or_default();
+    let code = message.split(':').next().unwrap_or_default().trim();
+
+    let is_code = !code.is_empty()
+        && code.len() <= 64
+        && code
+            .bytes()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit() || byte == b'_');
+    if is_code {
+        code
+    } else {
+        "other"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        assert_eq!(route("/graphql"), "/graphql");
+        assert_eq!(
+            route("/api/v1/songs/01GTESTABCDEFGHJKMNPQRSTVW/tags"),
+            "/api/v1/songs/:id/tags"
+        );
+        assert_eq!(route("/api/v1/songs/"), "/api/v1/songs");
+        assert_eq!(route("/"), "other");
+        assert_eq!(route("/wp-login.php"), "other");
+        assert_eq!(route("/api/v1/songs/a/b"), "other");
+    }
+
+    #[test]
+    fn test_error_code() {
+        assert_eq!(error_code("USER_NOT_FOUND"), "USER_NOT_FOUND");
+        assert_eq!(error_code("INVALID_PARAMETER: page"), "INVALID_PARAMETER");
+        assert_eq!(
+            error_code("UNAUTHORIZED occurred with status code: 401 Unauthorized"),
+            "UNAUTHORIZED"
+        );
+        assert_eq!(
+            error_code("Could not find the requested resource!"),
+            "other"
+        );
+        assert_eq!(error_code("random-name-1234"), "other");
+    }
+}
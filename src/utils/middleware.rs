@@ -1,44 +1,115 @@
-use super::error::{Error, ErrorResponse};
+use super::{
+    config::Config,
+    error::{Error, ErrorResponse},
+    metrics::Metrics,
+    rate_limit::{Budget, RateLimited, RateLimiter},
+};
 use crate::{
-    constants::{self, ALLOWED_CONTROL_HEADERS, ALLOWED_CONTROL_HOSTS, ALLOWED_CONTROL_METHODS},
-    models::user::AccessLevel,
+    constants::{
+        self, ALLOWED_CONTROL_HEADERS, ALLOWED_CONTROL_METHODS, APP_NAME, AUTH_API_KEY_PREFIX,
+    },
+    models::{api_key::ApiKey, user::AccessLevel},
 };
 use hyper::{
     header::{self, HeaderValue},
-    Body, Request, Response,
+    Body, Method, Request, Response, StatusCode,
 };
 use jsonwebtoken::{decode, Validation};
-use routerify::{ext::RequestExt, RouteError};
+use routerify::{ext::RequestExt, RequestInfo, RouteError};
 use serde::{Deserialize, Serialize};
-use std::io;
+use sqlx::PgPool;
+use std::{io, net::SocketAddr, sync::Arc, time::Instant};
 use tracing::{error, info};
 
-pub async fn setup_headers(mut req: Response<Body>) -> Result<Response<Body>, io::Error> {
-    let headers = req.headers_mut();
-
-    headers.insert(
-        header::ACCESS_CONTROL_ALLOW_ORIGIN,
-        HeaderValue::from_static(ALLOWED_CONTROL_HOSTS),
-    );
-
-    headers.insert(
-        header::ACCESS_CONTROL_ALLOW_HEADERS,
-        HeaderValue::from_static(ALLOWED_CONTROL_HEADERS),
-    );
+/// Adds the CORS headers for the origin of the request, if it's allowed.
+pub async fn setup_headers(
+    mut res: Response<Body>,
+    info: RequestInfo,
+) -> Result<Response<Body>, io::Error> {
+    let cors = &info.data::<Config>().unwrap().cors;
+    let origin = info
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok());
+    let headers = res.headers_mut();
 
-    headers.insert(
-        header::ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static(ALLOWED_CONTROL_METHODS),
-    );
+    if let Some(allowed) = cors.allow_origin(origin) {
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_str(allowed).unwrap(),
+        );
+        if cors.allow_credentials {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !cors.exposed_headers.is_empty() {
+            headers.insert(
+                header::ACCESS_CONTROL_EXPOSE_HEADERS,
+                HeaderValue::from_str(&cors.exposed_headers.join(", ")).unwrap(),
+            );
+        }
+    }
+    // The answer depends on the origin unless every origin gets `*`.
+    if cors.allow_origin(None).is_none() {
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
 
     headers.insert(
         header::SERVER,
         HeaderValue::from_static(constants::APP_NAME),
     );
 
+    Ok(res)
+}
+
+/// Answers CORS preflight requests, `setup_headers` adds whether the origin is allowed.
+pub async fn preflight(req: Request<Body>) -> Result<Response<Body>, io::Error> {
+    let cors = &req.data::<Config>().unwrap().cors;
+
+    Ok(Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            ALLOWED_CONTROL_METHODS,
+        )
+        .header(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            ALLOWED_CONTROL_HEADERS,
+        )
+        .header(header::ACCESS_CONTROL_MAX_AGE, cors.max_age)
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// When the request came in, for [`record_metrics`].
+#[derive(Clone, Copy)]
+struct RequestStart(Instant);
+
+pub async fn start_timer(req: Request<Body>) -> Result<Request<Body>, io::Error> {
+    req.set_context(RequestStart(Instant::now()));
     Ok(req)
 }
 
+/// Counts the response and how long it took, so it has to run after `start_timer`.
+pub async fn record_metrics(
+    res: Response<Body>,
+    info: RequestInfo,
+) -> Result<Response<Body>, io::Error> {
+    let metrics = info.data::<Arc<Metrics>>().unwrap();
+    if let Some(RequestStart(start)) = info.context::<RequestStart>() {
+        metrics.record_http(
+            info.method().as_str(),
+            info.uri().path(),
+            res.status().as_u16(),
+            start.elapsed(),
+        );
+    }
+
+    Ok(res)
+}
+
 pub async fn logger(req: Request<Body>) -> Result<Request<Body>, io::Error> {
     info!(
         "{} {} {}",
@@ -61,33 +132,59 @@ pub struct Claims {
     pub sid: String,
 }
 
+/// Details about the client that sent a request, handed to the GraphQL resolvers.
+#[derive(Debug, Clone)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,
+    pub user_agent: Option<String>,
+}
+
+impl ClientInfo {
+    pub fn from_request(req: &Request<Body>) -> Self {
+        Self {
+            addr: req.remote_addr(),
+            user_agent: req
+                .headers()
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string()),
+        }
+    }
+
+    pub fn ip_address(&self) -> String {
+        self.addr.ip().to_string()
+    }
+}
+
 // Implement an authentication middleware that checks for a valid JWT token in the Authorization header.
 // This uses routerify's middleware API.
 pub async fn auth(req: Request<Body>) -> Result<Request<Body>, io::Error> {
-    // Get the auth key from config and decode the token.
-    let config = req.data::<crate::config::Config>().unwrap();
-    let auth_key = jsonwebtoken::DecodingKey::from_base64_secret(&config.auth_key).unwrap();
-
     let auth_head = req.headers().get(header::AUTHORIZATION);
     match auth_head {
         Some(authorization_str) => {
-            let token = authorization_str
-                .to_str()
-                .unwrap()
-                .to_string()
-                .replace("Bearer ", "");
+            // Headers that aren't visible ASCII can't hold a token, those requests are anonymous.
+            let token = match authorization_str.to_str().ok() {
+                Some(authorization) => authorization.replace("Bearer ", ""),
+                None => return Ok(req),
+            };
 
             // Check if the token is empty.
             if token.is_empty() {
                 return Ok(req);
             }
 
-            // Validate the token.
-            let validation = Validation::default();
-            let claims = decode::<Claims>(&token, &auth_key, &validation).unwrap();
-
-            // Set the claims in the request's context.
-            req.set_context(claims.claims);
+            let config = req.data::<Config>().unwrap();
+            let db = req.data::<PgPool>().unwrap();
+            match authenticate(&token, config, db).await {
+                Ok(Some((claims, api_key))) => {
+                    req.set_context(claims);
+                    if let Some(api_key) = api_key {
+                        req.set_context(api_key);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => error!("Failed to authenticate request: {err}"),
+            }
         }
         None => {
             return Ok(req);
@@ -97,7 +194,128 @@ pub async fn auth(req: Request<Body>) -> Result<Request<Body>, io::Error> {
     Ok(req)
 }
 
+/// Finds out who a bearer token belongs to, it's either a JWT or an API key.
+///
+/// # Returns
+/// * `Option<(Claims, Option<ApiKey>)>` - `None` for tokens of revoked or expired sessions,
+///   and for unknown, revoked or expired API keys. Those are treated as anonymous requests.
+pub async fn authenticate(
+    token: &str,
+    config: &Config,
+    db: &PgPool,
+) -> Result<Option<(Claims, Option<ApiKey>)>, Error> {
+    // API keys are looked up in the database instead of being decoded.
+    if token.starts_with(AUTH_API_KEY_PREFIX) {
+        let result = api_key_claims(token, config, db).await?;
+        if result.is_none() {
+            info!("Ignoring unknown, revoked or expired API key");
+        }
+        return Ok(result.map(|(claims, api_key)| (claims, Some(api_key))));
+    }
+
+    // Get the auth key from config and decode the token.
+    // `Config::validate` made sure the key is base64 when the config was loaded.
+    let auth_key = jsonwebtoken::DecodingKey::from_base64_secret(&config.auth_key)?;
+    let claims = decode::<Claims>(token, &auth_key, &Validation::default())?.claims;
+
+    if !crate::database::session::is_session_active(&claims.sid, db).await? {
+        info!("Ignoring token of inactive session {}", claims.sid);
+        return Ok(None);
+    }
+
+    Ok(Some((claims, None)))
+}
+
+/// Builds the claims for a request authenticated with an API key, as if the user had logged in.
+///
+/// API keys don't belong to a session, so `sid` is left empty.
+async fn api_key_claims(
+    key: &str,
+    config: &Config,
+    db: &PgPool,
+) -> Result<Option<(Claims, ApiKey)>, Error> {
+    let api_key = match crate::database::api_key::authenticate_api_key(key, db).await? {
+        Some(api_key) => api_key,
+        None => return Ok(None),
+    };
+
+    let options = crate::models::user::Options {
+        id: Some(api_key.user_id.clone()),
+        email: None,
+        page: None,
+        per_page: None,
+    };
+    let user = crate::database::user::get_user(&options, db).await?;
+    if user.is_suspended() {
+        info!(
+            "Ignoring API key {} of suspended user {}",
+            api_key.id, user.id
+        );
+        return Ok(None);
+    }
+
+    let now = chrono::Utc::now().timestamp() as usize;
+    let claims = Claims {
+        iss: APP_NAME.to_string(),
+        aud: APP_NAME.to_string(),
+        iat: now,
+        nbf: now,
+        exp: api_key
+            .expires_at
+            .map(|expires_at| expires_at.timestamp() as usize)
+            .unwrap_or(usize::MAX),
+        ulid: user.id.to_string(),
+        access_level: user.effective_access_level(&config.two_factor),
+        sid: String::new(),
+    };
+
+    Ok(Some((claims, api_key)))
+}
+
+/// Throttles requests by the client they come from, so it has to run after `auth`.
+pub async fn rate_limit(req: Request<Body>) -> Result<Request<Body>, io::Error> {
+    // Orchestrators and Prometheus poll these all the time, from a single address.
+    // Preflight requests come along with the actual ones, which are counted.
+    if matches!(req.uri().path(), "/healthz" | "/readyz" | "/metrics")
+        || req.method() == Method::OPTIONS
+    {
+        return Ok(req);
+    }
+
+    let limiter = req.data::<Arc<RateLimiter>>().unwrap();
+    let key = rate_limit_key(&req);
+
+    if let Err(limited) = limiter.check(Budget::Request, &key) {
+        info!("Rate limited {key}");
+        return Err(io::Error::other(limited));
+    }
+
+    Ok(req)
+}
+
+/// Identifies the client a request is counted against: the API key or user if it's
+/// authenticated, the IP address otherwise.
+pub fn rate_limit_key(req: &Request<Body>) -> String {
+    match (req.context::<ApiKey>(), req.context::<Claims>()) {
+        (Some(api_key), _) => format!("key:{}", api_key.id),
+        (None, Some(claims)) => format!("user:{}", claims.ulid),
+        (None, None) => format!("ip:{}", req.remote_addr().ip()),
+    }
+}
+
 pub async fn handle_error(err: RouteError) -> Response<Body> {
+    let limited = err
+        .downcast_ref::<io::Error>()
+        .and_then(|err| err.get_ref())
+        .and_then(|err| err.downcast_ref::<RateLimited>());
+    if let Some(limited) = limited {
+        let json = serde_json::to_string(&ErrorResponse::from(Error::new(
+            "RATE_LIMITED",
+            hyper::StatusCode::TOO_MANY_REQUESTS,
+        )));
+        return limited.response(json.unwrap());
+    }
+
     error!("Error occurred while serving a request {err}");
 
     let err = err.downcast::<Error>().unwrap();
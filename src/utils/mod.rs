@@ -1,7 +1,22 @@
+pub mod cli;
 pub mod config;
+pub mod entity_events;
 pub mod error;
+pub mod listener;
+pub mod mailer;
+pub mod metrics;
 pub mod middleware;
+pub mod oidc;
+pub mod query_limits;
+pub mod rate_limit;
+pub mod replicas;
+pub mod response_cache;
+pub mod schema_diff;
 pub mod startup;
+pub mod telemetry;
+pub mod tls;
+pub mod token;
+pub mod totp;
 
 pub fn get_env(name: &str) -> Option<String> {
     std::env::var(name).ok()
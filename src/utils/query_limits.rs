@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use async_graphql::{
+    async_trait,
+    extensions::{Extension, ExtensionContext, ExtensionFactory, NextValidation},
+    ServerError, ValidationResult,
+};
+
+use super::{
+    config::{self, QueryLimit},
+    middleware::Claims,
+};
+use crate::{
+    constants::{QUERY_COST_LOOKUP, QUERY_COST_PAGE_SIZE},
+    models::api_key::ApiKey,
+};
+
+/// Cost of a field that looks up a single item in the database.
+pub fn lookup_cost(child_complexity: usize) -> usize {
+    QUERY_COST_LOOKUP.saturating_add(child_complexity)
+}
+
+/// Cost of a field that looks up a list of `size` items in the database.
+pub fn list_cost(size: usize, child_complexity: usize) -> usize {
+    QUERY_COST_LOOKUP.saturating_add(size.saturating_mul(child_complexity))
+}
+
+/// Number of items a paginated list is going to hold.
+pub fn page_size(per_page: Option<i32>) -> usize {
+    per_page.map_or(QUERY_COST_PAGE_SIZE, |per_page| per_page.max(1) as usize)
+}
+
+/// Rejects queries that nest too deep or cost too much before they're executed,
+/// with higher limits for authenticated clients.
+pub struct QueryLimits(config::QueryLimits);
+
+impl QueryLimits {
+    pub fn new(limits: &config::QueryLimits) -> Self {
+        Self(limits.clone())
+    }
+}
+
+impl ExtensionFactory for QueryLimits {
+    fn create(&self) -> Arc<dyn Extension> {
+        Arc::new(QueryLimitsExtension(self.0.clone()))
+    }
+}
+
+struct QueryLimitsExtension(config::QueryLimits);
+
+impl QueryLimitsExtension {
+    fn limit(&self, ctx: &ExtensionContext<'_>) -> QueryLimit {
+        if ctx.data_opt::<ApiKey>().is_some() {
+            self.0.api_key
+        } else if ctx.data_opt::<Claims>().is_some() {
+            self.0.authenticated
+        } else {
+            self.0.anonymous
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Extension for QueryLimitsExtension {
+    async fn validation(
+        &self,
+        ctx: &ExtensionContext<'_>,
+        next: NextValidation<'_>,
+    ) -> Result<ValidationResult, Vec<ServerError>> {
+        let result = next.run(ctx).await?;
+        let limit = self.limit(ctx);
+
+        if result.depth > limit.depth {
+            return Err(vec![ServerError::new(
+                format!(
+                    "QUERY_TOO_DEEP: depth of {} is over the limit of {}",
+                    result.depth, limit.depth
+                ),
+                None,
+            )]);
+        }
+
+        if result.complexity > limit.complexity {
+            return Err(vec![ServerError::new(
+                format!(
+                    "QUERY_TOO_COMPLEX: complexity of {} is over the limit of {}",
+                    result.complexity, limit.complexity
+                ),
+                None,
+            )]);
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        constants::APP_NAME, controllers::graphql::make_schema, models::user::AccessLevel,
+    };
+
+    fn limits() -> config::QueryLimits {
+        let limit = |depth, complexity| QueryLimit { depth, complexity };
+        config::QueryLimits {
+            anonymous: limit(3, 30),
+            authenticated: limit(4, 100),
+            api_key: limit(4, 100),
+        }
+    }
+
+    fn claims() -> Claims {
+        Claims {
+            iss: APP_NAME.to_string(),
+            aud: APP_NAME.to_string(),
+            iat: 0,
+            nbf: 0,
+            exp: usize::MAX,
+            ulid: String::new(),
+            access_level: AccessLevel::User,
+            sid: String::new(),
+        }
+    }
+
+    fn errors(response: async_graphql::Response) -> Vec<String> {
+        response.errors.into_iter().map(|err| err.message).collect()
+    }
+
+    #[tokio::test]
+    async fn test_query_limits() {
+        let schema = make_schema(&limits());
+
+        let response = schema.execute("{ __typename }").await;
+        assert!(response.errors.is_empty());
+
+        let query = "{ song(id: \"x\") { artists { name { romanized } } } }";
+        assert_eq!(
+            errors(schema.execute(query).await),
+            ["QUERY_TOO_DEEP: depth of 4 is over the limit of 3"]
+        );
+
+        // 10 for the song, plus 10 + 10 * 1 for each of the lists.
+        let query = "{ song(id: \"x\") { artists { id } releases { id } } }";
+        assert_eq!(
+            errors(schema.execute(query).await),
+            ["QUERY_TOO_COMPLEX: complexity of 50 is over the limit of 30"]
+        );
+
+        // Logged in users get more, the query only fails once it's executed without a database.
+        let db = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_millis(10))
+            .connect_lazy("postgres://localhost:1/none")
+            .unwrap();
+        let request = async_graphql::Request::new(query).data(claims()).data(db);
+        let response = schema.execute(request).await;
+        assert!(!errors(response)
+            .iter()
+            .any(|err| err.starts_with("QUERY_TOO")));
+    }
+}
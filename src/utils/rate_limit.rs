@@ -0,0 +1,273 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use hyper::{
+    header::{self, HeaderValue},
+    Body, Response, StatusCode,
+};
+
+use super::config;
+use crate::constants::RATE_LIMIT_MAX_KEYS;
+
+/// What a request is counted against, see [`config::RateLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Budget {
+    Request,
+    Query,
+    Mutation,
+    Login,
+}
+
+/// A client used up its budget or is locked out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    /// Seconds until the client may try again.
+    pub retry_after: u64,
+}
+
+impl RateLimited {
+    /// A `429 Too Many Requests` response with a `Retry-After` header.
+    pub fn response(&self, body: String) -> Response<Body> {
+        Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .header(header::RETRY_AFTER, self.header_value())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    pub fn header_value(&self) -> HeaderValue {
+        HeaderValue::from(self.retry_after)
+    }
+}
+
+impl Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "RATE_LIMITED, retry after {} seconds", self.retry_after)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Keeps count of requests and failed logins in memory, so limits apply per server instance.
+pub struct RateLimiter {
+    config: config::RateLimit,
+    windows: Mutex<HashMap<(Budget, String), Window>>,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &config::RateLimit) -> Self {
+        Self {
+            config: config.clone(),
+            windows: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request of the client against the budget.
+    ///
+    /// # Errors
+    /// * `RateLimited` - If the client already used up the budget of the current window.
+    pub fn check(&self, budget: Budget, key: &str) -> Result<(), RateLimited> {
+        self.check_at(budget, key, Instant::now())
+    }
+
+    /// Makes sure the client isn't locked out after too many failed logins.
+    pub fn check_lockout(&self, key: &str) -> Result<(), RateLimited> {
+        self.check_lockout_at(key, Instant::now())
+    }
+
+    /// Records a failed login, locking the client out once there were too many in a row.
+    pub fn record_failure(&self, key: &str) {
+        self.record_failure_at(key, Instant::now())
+    }
+
+    /// Forgets the failed logins of the client.
+    pub fn record_success(&self, key: &str) {
+        self.failures.lock().unwrap().remove(key);
+    }
+
+    fn limit(&self, budget: Budget) -> u32 {
+        match budget {
+            Budget::Request => self.config.requests,
+            Budget::Query => self.config.queries,
+            Budget::Mutation => self.config.mutations,
+            Budget::Login => self.config.logins,
+        }
+    }
+
+    fn check_at(&self, budget: Budget, key: &str, now: Instant) -> Result<(), RateLimited> {
+        let limit = self.limit(budget);
+        if !self.config.enabled || limit == 0 {
+            return Ok(());
+        }
+
+        let length = Duration::from_secs(self.config.window);
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= RATE_LIMIT_MAX_KEYS {
+            windows.retain(|_, window| now.duration_since(window.started) < length);
+        }
+
+        let window = windows.entry((budget, key.to_string())).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= length {
+            window.started = now;
+            window.count = 0;
+        }
+
+        if window.count >= limit {
+            return Err(RateLimited {
+                retry_after: seconds_until(window.started + length, now),
+            });
+        }
+
+        window.count += 1;
+        Ok(())
+    }
+
+    fn check_lockout_at(&self, key: &str, now: Instant) -> Result<(), RateLimited> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        match self.failures.lock().unwrap().get(key) {
+            Some(Failures {
+                locked_until: Some(until),
+                ..
+            }) if *until > now => Err(RateLimited {
+                retry_after: seconds_until(*until, now),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn record_failure_at(&self, key: &str, now: Instant) {
+        if !self.config.enabled || self.config.lockout_threshold == 0 {
+            return;
+        }
+
+        let forget_after = Duration::from_secs(self.config.lockout_max);
+        let mut failures = self.failures.lock().unwrap();
+        if failures.len() >= RATE_LIMIT_MAX_KEYS {
+            failures.retain(|_, failure| now.duration_since(failure.last) < forget_after);
+        }
+
+        let failure = failures.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+        if now.duration_since(failure.last) >= forget_after {
+            failure.count = 0;
+        }
+
+        failure.count += 1;
+        failure.last = now;
+        if failure.count >= self.config.lockout_threshold {
+            // Every failure past the threshold doubles the lockout.
+            let doublings = (failure.count - self.config.lockout_threshold).min(32);
+            let seconds = self
+                .config
+                .lockout_base
+                .saturating_mul(1 << doublings)
+                .min(self.config.lockout_max);
+            failure.locked_until = Some(now + Duration::from_secs(seconds));
+        }
+    }
+}
+
+/// Rounds up, so clients don't come back a moment too early.
+fn seconds_until(until: Instant, now: Instant) -> u64 {
+    let remaining = until.duration_since(now);
+    (remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(&config::RateLimit {
+            window: 60,
+            mutations: 2,
+            queries: 0,
+            lockout_threshold: 3,
+            lockout_base: 30,
+            lockout_max: 100,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_budget() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        assert!(limiter.check_at(Budget::Mutation, "ip:1", start).is_ok());
+        assert!(limiter.check_at(Budget::Mutation, "ip:1", start).is_ok());
+        assert_eq!(
+            limiter.check_at(Budget::Mutation, "ip:1", start + Duration::from_secs(20)),
+            Err(RateLimited { retry_after: 40 })
+        );
+        // Other clients and budgets are counted separately, a budget of 0 is unlimited.
+        assert!(limiter.check_at(Budget::Mutation, "ip:2", start).is_ok());
+        for _ in 0..10 {
+            assert!(limiter.check_at(Budget::Query, "ip:1", start).is_ok());
+        }
+        // The budget is back once the window has passed.
+        assert!(limiter
+            .check_at(Budget::Mutation, "ip:1", start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_lockout() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        limiter.record_failure_at("login", start);
+        limiter.record_failure_at("login", start);
+        assert!(limiter.check_lockout_at("login", start).is_ok());
+
+        limiter.record_failure_at("login", start);
+        assert_eq!(
+            limiter.check_lockout_at("login", start),
+            Err(RateLimited { retry_after: 30 })
+        );
+        assert!(limiter
+            .check_lockout_at("login", start + Duration::from_secs(30))
+            .is_ok());
+
+        // Every further failure doubles the lockout, up to the maximum.
+        limiter.record_failure_at("login", start);
+        assert_eq!(
+            limiter.check_lockout_at("login", start),
+            Err(RateLimited { retry_after: 60 })
+        );
+        limiter.record_failure_at("login", start);
+        assert_eq!(
+            limiter.check_lockout_at("login", start),
+            Err(RateLimited { retry_after: 100 })
+        );
+
+        limiter.record_success("login");
+        assert!(limiter.check_lockout_at("login", start).is_ok());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use sqlx::{postgres::PgPoolOptions, PgPool};
+use tracing::{info, warn};
+
+use super::config;
+use crate::constants::{DB_REPLICA_CHECK_INTERVAL, RATE_LIMIT_MAX_KEYS};
+
+struct Replica {
+    pool: PgPool,
+    /// Whether the last health check reached the replica.
+    healthy: AtomicBool,
+}
+
+/// Spreads read-only work over the read replicas, falling back to the primary while none of
+/// them are healthy. Clients that just changed something read from the primary for a while,
+/// so they see their change even while the replicas lag behind.
+pub struct Replicas {
+    replicas: Vec<Replica>,
+    next: AtomicUsize,
+    stick_for: Duration,
+    writes: Mutex<HashMap<String, Instant>>,
+}
+
+impl Replicas {
+    /// Connects lazily and starts checking the health of the replicas in the background,
+    /// they're only used once they answered a check.
+    pub fn connect(conf: &config::Db) -> Result<Arc<Self>, sqlx::Error> {
+        let replicas = conf
+            .replica_urls
+            .iter()
+            .map(|url| {
+                let pool = PgPoolOptions::new()
+                    .max_connections(conf.max_connections)
+                    .acquire_timeout(Duration::from_secs(conf.connect_timeout))
+                    .connect_lazy(url)?;
+                Ok(Replica {
+                    pool,
+                    healthy: AtomicBool::new(false),
+                })
+            })
+            .collect::<Result<_, sqlx::Error>>()?;
+
+        let replicas = Arc::new(Self {
+            replicas,
+            next: AtomicUsize::new(0),
+            stick_for: Duration::from_secs(conf.primary_after_write),
+            writes: Mutex::new(HashMap::new()),
+        });
+        if !replicas.replicas.is_empty() {
+            tokio::spawn(replicas.clone().check_health());
+        }
+
+        Ok(replicas)
+    }
+
+    /// A healthy replica the client can read from, `None` if it has to use the primary.
+    pub fn read_pool(&self, key: &str) -> Option<PgPool> {
+        self.read_pool_at(key, Instant::now())
+    }
+
+    /// Sends the reads of the client to the primary for a while.
+    pub fn record_write(&self, key: &str) {
+        self.record_write_at(key, Instant::now())
+    }
+
+    /// Closes the replica pools, like the primary on shutdown.
+    pub async fn close(&self) {
+        for replica in &self.replicas {
+            replica.pool.close().await;
+        }
+    }
+
+    fn read_pool_at(&self, key: &str, now: Instant) -> Option<PgPool> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+        if let Some(wrote) = self.writes.lock().unwrap().get(key) {
+            if now.duration_since(*wrote) < self.stick_for {
+                return None;
+            }
+        }
+
+        // Round robin, skipping the unhealthy replicas.
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..self.replicas.len())
+            .map(|offset| &self.replicas[(start + offset) % self.replicas.len()])
+            .find(|replica| replica.healthy.load(Ordering::Relaxed))
+            .map(|replica| replica.pool.clone())
+    }
+
+    fn record_write_at(&self, key: &str, now: Instant) {
+        if self.replicas.is_empty() || self.stick_for.is_zero() {
+            return;
+        }
+
+        let mut writes = self.writes.lock().unwrap();
+        if writes.len() >= RATE_LIMIT_MAX_KEYS {
+            writes.retain(|_, wrote| now.duration_since(*wrote) < self.stick_for);
+        }
+        writes.insert(key.to_string(), now);
+    }
+
+    async fn check_health(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(DB_REPLICA_CHECK_INTERVAL));
+        let mut first = true;
+
+        loop {
+            interval.tick().await;
+
+            for (i, replica) in self.replicas.iter().enumerate() {
+                // Stops when the pool closes on shutdown, instead of waiting for a slow check.
+                let check = sqlx::query("SELECT 1").execute(&replica.pool);
+                let Ok(result) = replica.pool.close_event().do_until(check).await else {
+                    return;
+                };
+
+                let healthy = match result {
+                    Ok(_) => true,
+                    Err(err) => {
+                        if first || replica.healthy.load(Ordering::Relaxed) {
+                            warn!("Read replica {i} is unreachable, not reading from it: {err}");
+                        }
+                        false
+                    }
+                };
+                if healthy && !replica.healthy.load(Ordering::Relaxed) {
+                    info!("Read replica {i} is healthy, reading from it");
+                }
+                replica.healthy.store(healthy, Ordering::Relaxed);
+            }
+            first = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_pool() {
+        let conf = config::Db {
+            replica_urls: vec![
+                "postgres://localhost/a".to_string(),
+                "postgres://localhost/b".to_string(),
+            ],
+            primary_after_write: 5,
+            ..config::Db::default()
+        };
+        let replicas = Replicas {
+            replicas: conf
+                .replica_urls
+                .iter()
+                .map(|url| Replica {
+                    pool: PgPoolOptions::new().connect_lazy(url).unwrap(),
+                    healthy: AtomicBool::new(true),
+                })
+                .collect(),
+            next: AtomicUsize::new(0),
+            stick_for: Duration::from_secs(conf.primary_after_write),
+            writes: Mutex::new(HashMap::new()),
+        };
+        let now = Instant::now();
+
+        assert!(replicas.read_pool_at("ip:1", now).is_some());
+
+        // Only the healthy replica is used.
+        replicas.replicas[0].healthy.store(false, Ordering::Relaxed);
+        for _ in 0..3 {
+            let pool = replicas.read_pool_at("ip:1", now).unwrap();
+            assert_eq!(pool.connect_options().get_database(), Some("b"));
+        }
+
+        // The client that wrote reads from the primary for a while, others don't.
+        replicas.record_write_at("ip:1", now);
+        assert!(replicas
+            .read_pool_at("ip:1", now + Duration::from_secs(4))
+            .is_none());
+        assert!(replicas.read_pool_at("ip:2", now).is_some());
+        assert!(replicas
+            .read_pool_at("ip:1", now + Duration::from_secs(5))
+            .is_some());
+
+        replicas.replicas[1].healthy.store(false, Ordering::Relaxed);
+        assert!(replicas.read_pool_at("ip:2", now).is_none());
+    }
+}
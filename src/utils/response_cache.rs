@@ -0,0 +1,170 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+
+use super::{config, entity_events::EntityEvents, token::hash_token};
+
+struct Entry {
+    body: String,
+    stored: Instant,
+}
+
+struct Entries {
+    /// Bumped whenever the cache is cleared, so responses that were being executed while
+    /// something changed aren't stored afterwards.
+    generation: u64,
+    responses: HashMap<String, Entry>,
+}
+
+/// Keeps responses to anonymous queries in memory, until a write could have changed them
+/// or they're `max_age` seconds old.
+pub struct ResponseCache {
+    config: config::ResponseCache,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    /// Creates the cache, clearing it whenever a song, artist or release changes on any instance.
+    pub fn new(config: &config::ResponseCache, events: &EntityEvents) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            config: config.clone(),
+            entries: Mutex::new(Entries {
+                generation: 0,
+                responses: HashMap::new(),
+            }),
+        });
+
+        let mut changes = Box::pin(events.subscribe());
+        let cleared = cache.clone();
+        tokio::spawn(async move {
+            while changes.next().await.is_some() {
+                cleared.clear();
+            }
+        });
+
+        cache
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Identifies the request by everything that goes into its response.
+    pub fn key(request: &async_graphql::Request) -> String {
+        hash_token(&format!(
+            "{}\n{}\n{}",
+            request.operation_name.as_deref().unwrap_or_default(),
+            serde_json::to_string(&request.variables).unwrap(),
+            request.query
+        ))
+    }
+
+    /// Returns the cached body of the response to the request, if it's still fresh.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .responses
+            .get(key)
+            .filter(|entry| entry.stored.elapsed() < self.max_age())
+            .map(|entry| entry.body.clone())
+    }
+
+    /// The generation to pass to [`ResponseCache::insert`], taken before executing the request.
+    pub fn generation(&self) -> u64 {
+        self.entries.lock().unwrap().generation
+    }
+
+    /// Stores the body of a response, unless the cache was cleared since `generation`.
+    /// Once full, new responses are only stored after old ones expire.
+    pub fn insert(&self, key: String, body: String, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if !self.config.enabled || entries.generation != generation {
+            return;
+        }
+
+        let max_age = self.max_age();
+        if entries.responses.len() >= self.config.max_entries {
+            entries
+                .responses
+                .retain(|_, entry| entry.stored.elapsed() < max_age);
+            if entries.responses.len() >= self.config.max_entries {
+                return;
+            }
+        }
+
+        entries.responses.insert(
+            key,
+            Entry {
+                body,
+                stored: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops all responses, called after writes.
+    pub fn clear(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.generation += 1;
+        entries.responses.clear();
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.config.max_age)
+    }
+}
+
+/// Strong `ETag` of a response body.
+pub fn etag(body: &str) -> String {
+    format!("\"{}\"", hash_token(body))
+}
+
+/// Whether an `If-None-Match` header matches the `ETag`, comparing weakly like GET requests should.
+pub fn matches_etag(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache() -> ResponseCache {
+        ResponseCache {
+            config: config::ResponseCache::default(),
+            entries: Mutex::new(Entries {
+                generation: 0,
+                responses: HashMap::new(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_cleared_while_executing() {
+        let cache = cache();
+        let generation = cache.generation();
+        cache.insert("a".to_string(), "{}".to_string(), generation);
+        assert_eq!(cache.get("a").as_deref(), Some("{}"));
+
+        // A mutation clears the cache while another query is still running.
+        let generation = cache.generation();
+        cache.clear();
+        cache.insert("b".to_string(), "{}".to_string(), generation);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.get("b"), None);
+    }
+
+    #[test]
+    fn test_matches_etag() {
+        let etag = etag("{}");
+        assert!(matches_etag(&etag, &etag));
+        assert!(matches_etag(&format!("\"other\", W/{etag}"), &etag));
+        assert!(matches_etag("*", &etag));
+        assert!(!matches_etag("\"other\"", &etag));
+    }
+}
@@ -0,0 +1,339 @@
+use std::{collections::HashMap, fmt::Display, fs};
+
+use async_graphql::parser::{
+    parse_schema,
+    types::{
+        BaseType, FieldDefinition, InputValueDefinition, Type, TypeDefinition, TypeKind,
+        TypeSystemDefinition,
+    },
+    Positioned,
+};
+
+use super::config::QueryLimits;
+use crate::controllers::graphql::make_schema;
+
+/// A difference between two versions of the schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Queries that worked against the old schema may fail against the new one.
+    pub breaking: bool,
+    pub message: String,
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.breaking { "BREAKING" } else { "change" };
+        write!(f, "{kind}: {}", self.message)
+    }
+}
+
+/// The SDL of the schema the server serves.
+pub fn sdl() -> String {
+    make_schema(&QueryLimits::default()).sdl()
+}
+
+/// Compares the schema against the snapshot at `path`, returning the exit code.
+///
+/// Fails if the snapshot is out of date, listing the changes and which of them break
+/// existing queries.
+pub fn check(path: &str) -> i32 {
+    let snapshot = match fs::read_to_string(path) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            eprintln!("Failed to read the schema snapshot {path}: {err}");
+            return 1;
+        }
+    };
+
+    let changes = match diff(&snapshot, &sdl()) {
+        Ok(changes) => changes,
+        Err(err) => {
+            eprintln!("Failed to parse the schema snapshot {path}: {err}");
+            return 1;
+        }
+    };
+    if changes.is_empty() {
+        println!("The schema matches {path}.");
+        return 0;
+    }
+
+    for change in &changes {
+        println!("{change}");
+    }
+    let breaking = changes.iter().filter(|change| change.breaking).count();
+    println!(
+        "\n{} changes, {breaking} breaking. Update the snapshot with `schema print > {path}`.",
+        changes.len()
+    );
+    1
+}
+
+/// Lists the changes from the `old` to the `new` SDL.
+pub fn diff(old: &str, new: &str) -> Result<Vec<Change>, String> {
+    let old = types(old)?;
+    let new = types(new)?;
+    let mut changes = Changes::default();
+
+    for (name, old_type) in &old {
+        let Some(new_type) = new.get(name) else {
+            changes.breaking(format!("type {name} was removed"));
+            continue;
+        };
+
+        match (&old_type.kind, &new_type.kind) {
+            (TypeKind::Object(old_object), TypeKind::Object(new_object)) => {
+                diff_fields(name, &old_object.fields, &new_object.fields, &mut changes)
+            }
+            (TypeKind::Interface(old_interface), TypeKind::Interface(new_interface)) => {
+                diff_fields(
+                    name,
+                    &old_interface.fields,
+                    &new_interface.fields,
+                    &mut changes,
+                )
+            }
+            (TypeKind::InputObject(old_input), TypeKind::InputObject(new_input)) => diff_inputs(
+                name,
+                "field",
+                &old_input.fields,
+                &new_input.fields,
+                &mut changes,
+            ),
+            (TypeKind::Enum(old_enum), TypeKind::Enum(new_enum)) => {
+                let old_values = names(&old_enum.values, |value| &value.value.node);
+                let new_values = names(&new_enum.values, |value| &value.value.node);
+                for value in old_values
+                    .keys()
+                    .filter(|value| !new_values.contains_key(*value))
+                {
+                    changes.breaking(format!("enum value {name}.{value} was removed"));
+                }
+                for value in new_values
+                    .keys()
+                    .filter(|value| !old_values.contains_key(*value))
+                {
+                    changes.safe(format!("enum value {name}.{value} was added"));
+                }
+            }
+            (TypeKind::Union(old_union), TypeKind::Union(new_union)) => {
+                let old_members = names(&old_union.members, |member| member);
+                let new_members = names(&new_union.members, |member| member);
+                for member in old_members
+                    .keys()
+                    .filter(|member| !new_members.contains_key(*member))
+                {
+                    changes.breaking(format!("{member} was removed from union {name}"));
+                }
+                for member in new_members
+                    .keys()
+                    .filter(|member| !old_members.contains_key(*member))
+                {
+                    changes.safe(format!("{member} was added to union {name}"));
+                }
+            }
+            (TypeKind::Scalar, TypeKind::Scalar) => {}
+            _ => changes.breaking(format!("type {name} changed its kind")),
+        }
+    }
+
+    for name in new.keys().filter(|name| !old.contains_key(*name)) {
+        changes.safe(format!("type {name} was added"));
+    }
+
+    let mut changes = changes.0;
+    changes.sort_by(|a, b| b.breaking.cmp(&a.breaking).then(a.message.cmp(&b.message)));
+    Ok(changes)
+}
+
+#[derive(Default)]
+struct Changes(Vec<Change>);
+
+impl Changes {
+    fn breaking(&mut self, message: String) {
+        self.0.push(Change {
+            breaking: true,
+            message,
+        });
+    }
+
+    fn safe(&mut self, message: String) {
+        self.0.push(Change {
+            breaking: false,
+            message,
+        });
+    }
+}
+
+fn types(sdl: &str) -> Result<HashMap<String, TypeDefinition>, String> {
+    let document = parse_schema(sdl).map_err(|err| err.to_string())?;
+
+    Ok(document
+        .definitions
+        .into_iter()
+        .filter_map(|definition| match definition {
+            TypeSystemDefinition::Type(definition) => {
+                Some((definition.node.name.node.to_string(), definition.node))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+fn names<'a, T>(
+    items: &'a [Positioned<T>],
+    name: impl Fn(&'a T) -> &'a async_graphql::Name,
+) -> HashMap<String, &'a T> {
+    items
+        .iter()
+        .map(|item| (name(&item.node).to_string(), &item.node))
+        .collect()
+}
+
+fn diff_fields(
+    type_name: &str,
+    old: &[Positioned<FieldDefinition>],
+    new: &[Positioned<FieldDefinition>],
+    changes: &mut Changes,
+) {
+    let old = names(old, |field| &field.name.node);
+    let new = names(new, |field| &field.name.node);
+
+    for (name, old_field) in &old {
+        let Some(new_field) = new.get(name) else {
+            changes.breaking(format!("field {type_name}.{name} was removed"));
+            continue;
+        };
+
+        let (old_type, new_type) = (&old_field.ty.node, &new_field.ty.node);
+        if old_type != new_type {
+            let message = format!("field {type_name}.{name} changed from {old_type} to {new_type}");
+            // Clients can handle a field that's never null, but not one that suddenly can be.
+            if is_stricter_or_equal(new_type, old_type) {
+                changes.safe(message);
+            } else {
+                changes.breaking(message);
+            }
+        }
+
+        let field = format!("{type_name}.{name}");
+        diff_inputs(
+            &field,
+            "argument",
+            &old_field.arguments,
+            &new_field.arguments,
+            changes,
+        );
+    }
+
+    for name in new.keys().filter(|name| !old.contains_key(*name)) {
+        changes.safe(format!("field {type_name}.{name} was added"));
+    }
+}
+
+/// Compares the arguments of a field or the fields of an input object.
+fn diff_inputs(
+    parent: &str,
+    kind: &str,
+    old: &[Positioned<InputValueDefinition>],
+    new: &[Positioned<InputValueDefinition>],
+    changes: &mut Changes,
+) {
+    let old = names(old, |input| &input.name.node);
+    let new = names(new, |input| &input.name.node);
+
+    for (name, old_input) in &old {
+        let Some(new_input) = new.get(name) else {
+            changes.breaking(format!("{kind} {parent}.{name} was removed"));
+            continue;
+        };
+
+        let (old_type, new_type) = (&old_input.ty.node, &new_input.ty.node);
+        if old_type != new_type {
+            let message = format!("{kind} {parent}.{name} changed from {old_type} to {new_type}");
+            // Clients can leave out an input that became optional, but have to send one that
+            // became required.
+            if is_stricter_or_equal(old_type, new_type) {
+                changes.safe(message);
+            } else {
+                changes.breaking(message);
+            }
+        }
+    }
+
+    for (name, new_input) in new.iter().filter(|(name, _)| !old.contains_key(*name)) {
+        if new_input.ty.node.nullable || new_input.default_value.is_some() {
+            changes.safe(format!("{kind} {parent}.{name} was added"));
+        } else {
+            changes.breaking(format!("required {kind} {parent}.{name} was added"));
+        }
+    }
+}
+
+/// Whether `stricter` is the same type as `looser`, only non-null in more places.
+fn is_stricter_or_equal(stricter: &Type, looser: &Type) -> bool {
+    if stricter.nullable && !looser.nullable {
+        return false;
+    }
+
+    match (&stricter.base, &looser.base) {
+        (BaseType::Named(stricter), BaseType::Named(looser)) => stricter == looser,
+        (BaseType::List(stricter), BaseType::List(looser)) => {
+            is_stricter_or_equal(stricter, looser)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::SCHEMA_SNAPSHOT_PATH;
+
+    fn messages(old: &str, new: &str) -> Vec<String> {
+        diff(old, new)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_is_up_to_date() {
+        let snapshot = include_str!("../../schema.graphql");
+        assert!(
+            diff(snapshot, &sdl()).unwrap().is_empty(),
+            "the schema changed, run `schema check` and update {SCHEMA_SNAPSHOT_PATH}"
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = r#"
+            type Song { id: String! name: String tags: [String!]! }
+            type Query { song(id: String, search: String): Song! removed: Int }
+            enum Kind { A B }
+        "#;
+        let new = r#"
+            type Song { id: String name: String! tags: [String!]! added: Int }
+            type Query { song(id: String!, genre: String, page: Int!): Song! }
+            enum Kind { A C }
+        "#;
+
+        assert_eq!(
+            messages(old, new),
+            [
+                "BREAKING: argument Query.song.id changed from String to String!",
+                "BREAKING: argument Query.song.search was removed",
+                "BREAKING: enum value Kind.B was removed",
+                "BREAKING: field Query.removed was removed",
+                "BREAKING: field Song.id changed from String! to String",
+                "BREAKING: required argument Query.song.page was added",
+                "change: argument Query.song.genre was added",
+                "change: enum value Kind.C was added",
+                "change: field Song.added was added",
+                "change: field Song.name changed from String to String!",
+            ]
+        );
+        assert!(diff(new, new).unwrap().is_empty());
+    }
+}
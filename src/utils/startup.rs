@@ -1,97 +1,227 @@
-use crate::{controllers, models::user::AccessLevel, utils::middleware};
-use hyper::{server::conn::AddrIncoming, Body, Server};
-use routerify::{Middleware, Router, RouterService};
-use sqlx::{postgres::PgPoolOptions, Row};
-use tracing::Level;
-use std::{
-    io,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    str::FromStr,
-    sync::Arc, env,
+use crate::{
+    controllers,
+    database::query_span,
+    models::user::AccessLevel,
+    utils::{
+        config::Config,
+        entity_events::EntityEvents,
+        error::Error,
+        listener::{Connection, Listener},
+        mailer::Mailer,
+        metrics::Metrics,
+        middleware,
+        oidc::OidcClient,
+        rate_limit::RateLimiter,
+        replicas::Replicas,
+        response_cache::ResponseCache,
+        telemetry,
+        token::generate_password,
+    },
 };
-use tracing_subscriber::FmtSubscriber;
+use hyper::{
+    service::{make_service_fn, service_fn, Service},
+    Body, Request, Server,
+};
+use routerify::{Middleware, RequestServiceBuilder, RouteError, Router};
+use sqlx::{
+    migrate::{Migrate, Migrator},
+    postgres::PgPoolOptions,
+    PgPool, Row,
+};
+use std::{convert::Infallible, future::Future, io, sync::Arc};
+use tokio::signal;
+use tracing::{info, warn, Instrument};
 
-pub type ServerStart = Server<AddrIncoming, RouterService<Body, io::Error>>;
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
-pub async fn up(conf: super::config::Config) -> (ServerStart, SocketAddr) {
-    let subscriber = FmtSubscriber::builder().with_max_level(get_trace_level()).finish();
-    tracing::subscriber::set_global_default(subscriber).unwrap();
+/// Versions of the applied migrations, oldest first.
+pub async fn applied_migrations(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut versions: Vec<i64> = pool
+        .acquire()
+        .await?
+        .list_applied_migrations()
+        .await?
+        .iter()
+        .map(|migration| migration.version)
+        .collect();
+    versions.sort_unstable();
+
+    Ok(versions)
+}
+
+/// Versions of the migrations the binary has that aren't applied yet.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<i64>, sqlx::Error> {
+    let applied = applied_migrations(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
 
-    let pool = PgPoolOptions::new()
+pub async fn connect(conf: &Config) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
         .max_connections(conf.db.max_connections)
         .acquire_timeout(std::time::Duration::from_secs(conf.db.connect_timeout))
         .connect(&conf.db.url)
         .await
-        .unwrap();
-
-    // Run migrations
-    sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+}
 
-    // Check if this is the first run by checking if the admin user exists
+/// Creates an admin on the first run, while there's no admin at all.
+///
+/// Without a configured `default_admin_password`, a random one is generated and printed once.
+pub async fn bootstrap_admin(conf: &Config, pool: &PgPool) -> Result<(), Error> {
     let admin_exists = sqlx::query(
         r#"
         SELECT EXISTS (
             SELECT 1
             FROM users
-            WHERE username = 'admin'
+            WHERE access_level = 'Admin'
         )
         "#,
     )
-    .fetch_one(&pool)
-    .await
-    .unwrap()
+    .fetch_one(pool)
+    .instrument(query_span(
+        "SELECT EXISTS (SELECT 1 FROM users WHERE access_level = 'Admin')",
+    ))
+    .await?
     .get::<bool, _>(0);
 
-    // If the admin user doesn't exist, create it
+    if admin_exists {
+        return Ok(());
+    }
 
-    if !admin_exists {
-        let admin_email = "admin@localhost".to_string();
-        let admin_access_level = AccessLevel::Admin;
+    let generated = conf.default_admin_password.is_empty();
+    let password = if generated {
+        generate_password()
+    } else {
+        conf.default_admin_password.clone()
+    };
 
-        crate::database::user::create_user(
-            admin_email,
-            conf.default_admin_username.clone(),
-            conf.default_admin_password.clone(),
-            admin_access_level,
-            &pool,
-        )
-        .await
-        .unwrap();
+    let result = crate::database::user::create_user(
+        "admin@localhost".to_string(),
+        conf.default_admin_username.clone(),
+        password.clone(),
+        AccessLevel::Admin,
+        pool,
+    )
+    .await;
+
+    match result {
+        Ok(_) => {}
+        // Another instance starting at the same time got there first, or the name is taken.
+        Err(err) if err.message == "USER_ALREADY_EXISTS" => {
+            warn!(
+                "Not creating the admin {}, a user with that name already exists",
+                conf.default_admin_username
+            );
+            return Ok(());
+        }
+        Err(err) => return Err(err),
     }
 
-    let schema = Arc::new(crate::controllers::graphql::make_schema());
+    info!("Created the admin {}", conf.default_admin_username);
+    if generated {
+        println!(
+            "Generated the password of the admin {}, it won't be shown again: {password}",
+            conf.default_admin_username
+        );
+    }
+
+    Ok(())
+}
+
+/// Builds the server, which stops accepting connections once `shutdown` completes and
+/// finishes when the in-flight requests are done. Also returns where it listens.
+pub async fn up(
+    conf: Config,
+    pool: PgPool,
+    replicas: Arc<Replicas>,
+    shutdown: impl Future<Output = ()>,
+) -> io::Result<(impl Future<Output = hyper::Result<()>>, String)> {
+    let schema = Arc::new(crate::controllers::graphql::make_schema(&conf.query_limits));
+    let mailer = Arc::new(Mailer::from_config(&conf.mail).unwrap());
+    let oidc = Arc::new(OidcClient::from_config(&conf.oidc));
+    let rate_limiter = Arc::new(RateLimiter::new(&conf.rate_limit));
+    let entity_events = EntityEvents::listen(&pool).await.unwrap();
+    let response_cache = ResponseCache::new(&conf.response_cache, &entity_events);
+    let metrics = Metrics::new(conf.db.max_connections);
 
     let router: Router<Body, io::Error> = Router::builder()
         .data(schema)
         .data(pool)
+        .data(replicas)
+        .data(mailer)
+        .data(oidc)
+        .data(rate_limiter)
+        .data(entity_events)
+        .data(response_cache)
+        .data(metrics)
         .data(conf.clone())
+        .middleware(Middleware::pre(middleware::start_timer))
         .middleware(Middleware::pre(middleware::logger))
-        .middleware(Middleware::post(middleware::setup_headers))
+        .middleware(Middleware::post_with_info(middleware::setup_headers))
+        .middleware(Middleware::post_with_info(middleware::record_metrics))
         .middleware(Middleware::pre(middleware::auth))
+        .middleware(Middleware::pre(middleware::rate_limit))
         .scope("/", controllers::handle_routes())
         .err_handler(middleware::handle_error)
         .build()
         .unwrap();
 
-    let service = RouterService::new(router).unwrap();
-    let ip = IpAddr::V4(Ipv4Addr::from_str(&conf.ip.to_string()).unwrap());
-    let addr = SocketAddr::new(ip, conf.port);
-    let server = Server::bind(&addr).serve(service);
+    let builder = Arc::new(RequestServiceBuilder::new(router).unwrap());
+    let service = make_service_fn(move |conn| {
+        let mut service = builder.build(Connection::remote_addr(conn));
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req: Request<Body>| {
+                // Handlers and logs see the same id the client gets back.
+                let request_id = telemetry::request_id(&req);
+                req.headers_mut()
+                    .insert(telemetry::REQUEST_ID_HEADER, request_id.clone());
+                let span = telemetry::request_span(&req, request_id.to_str().unwrap());
+
+                let response = service.call(req).instrument(span.clone());
+                async move {
+                    let mut res = response.await?;
+                    span.record("http.status_code", res.status().as_u16());
+                    res.headers_mut()
+                        .insert(telemetry::REQUEST_ID_HEADER, request_id);
+                    Ok::<_, RouteError>(res)
+                }
+            }))
+        }
+    });
+
+    let listener = Listener::bind(&conf)?;
+    let url = listener.url(conf.tls.enabled()).to_string();
+    let server = Server::builder(listener.incoming(&conf)?)
+        .http1_only(!conf.http2)
+        .serve(service)
+        .with_graceful_shutdown(shutdown);
 
-    (server, addr)
+    Ok((server, url))
 }
 
-fn get_trace_level() -> Level {
-    // Get the value of the RUST_LOG environment variable
-    let level = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
-
-    // Convert the string to lowercase and then match it
-    match level.to_lowercase().as_str() {
-        "trace" => Level::TRACE,
-        "debug" => Level::DEBUG,
-        "info" => Level::INFO,
-        "warn" => Level::WARN,
-        "error" => Level::ERROR,
-        _ => Level::INFO,
+/// Completes on Ctrl+C or, on Unix, SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
-}
\ No newline at end of file
+}
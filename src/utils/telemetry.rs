@@ -0,0 +1,152 @@
+use hyper::{header::HeaderValue, http::HeaderMap, Request};
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    sdk::{
+        propagation::TraceContextPropagator,
+        trace::{self, Sampler},
+        Resource,
+    },
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use std::{env, io};
+use tracing::{field, info_span, Level, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter::LevelFilter, prelude::*};
+use ulid::Ulid;
+
+use super::{config, metrics};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Logs go to stderr, leaving stdout to the output of commands like `export`.
+/// Spans are also exported over OTLP when `tracing.otlp_endpoint` is set.
+pub fn init(conf: &config::Tracing) -> Result<(), opentelemetry::trace::TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let otlp = if conf.otlp_endpoint.is_empty() {
+        None
+    } else {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(&conf.otlp_endpoint),
+            )
+            .with_trace_config(
+                trace::config()
+                    .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                        conf.sample_ratio,
+                    ))))
+                    .with_resource(Resource::new(vec![KeyValue::new(
+                        "service.name",
+                        conf.service_name.clone(),
+                    )])),
+            )
+            .install_batch(opentelemetry::runtime::Tokio)?;
+        Some(tracing_opentelemetry::layer().with_tracer(tracer))
+    };
+
+    tracing_subscriber::registry()
+        .with(LevelFilter::from_level(get_trace_level()))
+        .with(tracing_subscriber::fmt::layer().with_writer(io::stderr))
+        .with(otlp)
+        .init();
+
+    Ok(())
+}
+
+/// Exports the spans that are still buffered.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Span of an HTTP request, continuing the trace of the caller if it sent a `traceparent`.
+pub fn request_span<B>(req: &Request<B>, request_id: &str) -> Span {
+    let route = metrics::route(req.uri().path());
+    let span = info_span!(
+        "http_request",
+        otel.name = %format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        http.method = %req.method(),
+        http.target = %req.uri().path(),
+        http.route = route,
+        http.status_code = field::Empty,
+        request_id,
+    );
+
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(req.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
+
+/// The `X-Request-Id` the client sent, or a new one when it's missing or not safe to log.
+pub fn request_id<B>(req: &Request<B>) -> HeaderValue {
+    req.headers()
+        .get(REQUEST_ID_HEADER)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= 128
+                && id
+                    .as_bytes()
+                    .iter()
+                    .all(|byte| byte.is_ascii_alphanumeric() || b"._-".contains(byte))
+        })
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_str(&Ulid::new().to_string()).unwrap())
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+fn get_trace_level() -> Level {
+    // Get the value of the RUST_LOG environment variable
+    let level = env::var("RUST_LOG").unwrap_or_else(|_| "info".to_string());
+
+    // Convert the string to lowercase and then match it
+    match level.to_lowercase().as_str() {
+        "trace" => Level::TRACE,
+        "debug" => Level::DEBUG,
+        "info" => Level::INFO,
+        "warn" => Level::WARN,
+        "error" => Level::ERROR,
+        _ => Level::INFO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        let req = Request::builder()
+            .header(REQUEST_ID_HEADER, "abc-123.x_y")
+            .body(())
+            .unwrap();
+        assert_eq!(request_id(&req), "abc-123.x_y");
+
+        let req = Request::builder()
+            .header(REQUEST_ID_HEADER, "bad id")
+            .body(())
+            .unwrap();
+        assert_eq!(request_id(&req).len(), 26);
+
+        let req = Request::builder().body(()).unwrap();
+        assert_eq!(request_id(&req).len(), 26);
+    }
+}
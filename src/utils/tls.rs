@@ -0,0 +1,126 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use tokio_rustls::{
+    rustls::{
+        server::{ClientHello, ResolvesServerCert},
+        sign::{self, CertifiedKey},
+        Certificate, PrivateKey, ServerConfig,
+    },
+    TlsAcceptor,
+};
+use tracing::{info, warn};
+
+use super::config;
+use crate::constants::TLS_RELOAD_INTERVAL;
+
+/// Hands out the current certificate, which [`watch`] swaps when the files change.
+struct CertResolver {
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().unwrap().clone())
+    }
+}
+
+/// Loads the certificate and key, then keeps reloading them whenever they change.
+///
+/// # Arguments
+/// * `http2` - Offer HTTP/2 to clients through ALPN.
+pub fn acceptor(conf: &config::Tls, http2: bool) -> io::Result<TlsAcceptor> {
+    let resolver = Arc::new(CertResolver {
+        key: RwLock::new(Arc::new(load(conf)?)),
+    });
+
+    let mut server_config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver.clone());
+    server_config.alpn_protocols = if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+
+    tokio::spawn(watch(conf.clone(), resolver));
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+/// Polls the modification times of the files, a broken new certificate keeps the old one in use.
+async fn watch(conf: config::Tls, resolver: Arc<CertResolver>) {
+    let mut loaded = modified(&conf);
+    let mut interval = tokio::time::interval(Duration::from_secs(TLS_RELOAD_INTERVAL));
+
+    loop {
+        interval.tick().await;
+
+        let current = modified(&conf);
+        if current == loaded {
+            continue;
+        }
+        loaded = current;
+
+        match load(&conf) {
+            Ok(key) => {
+                *resolver.key.write().unwrap() = Arc::new(key);
+                info!("Reloaded the TLS certificate from {}", conf.cert_path);
+            }
+            Err(err) => {
+                warn!("Keeping the current TLS certificate, loading the new one failed: {err}")
+            }
+        }
+    }
+}
+
+fn modified(conf: &config::Tls) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &str| {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    };
+    Some((modified(&conf.cert_path)?, modified(&conf.key_path)?))
+}
+
+fn load(conf: &config::Tls) -> io::Result<CertifiedKey> {
+    let invalid = |path: &str, message: &str| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {message}"))
+    };
+
+    let open = |path: &str| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|err| io::Error::new(err.kind(), format!("{path}: {err}")))
+    };
+
+    let certs = rustls_pemfile::certs(&mut open(&conf.cert_path)?)?;
+    if certs.is_empty() {
+        return Err(invalid(&conf.cert_path, "no certificate found"));
+    }
+
+    let mut reader = open(&conf.key_path)?;
+    let key = loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(
+                rustls_pemfile::Item::PKCS8Key(key)
+                | rustls_pemfile::Item::RSAKey(key)
+                | rustls_pemfile::Item::ECKey(key),
+            ) => break key,
+            Some(_) => continue,
+            None => return Err(invalid(&conf.key_path, "no private key found")),
+        }
+    };
+    let key = sign::any_supported_type(&PrivateKey(key))
+        .map_err(|_| invalid(&conf.key_path, "unsupported private key"))?;
+
+    Ok(CertifiedKey::new(
+        certs.into_iter().map(Certificate).collect(),
+        key,
+    ))
+}
@@ -0,0 +1,119 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::StatusCode;
+use totp_rs::{Algorithm, Secret, TOTP};
+use tracing::error;
+
+use super::error::Error;
+use crate::constants::{TOTP_DIGITS, TOTP_STEP};
+
+/// Generates a new secret, base32 encoded the way authenticator apps expect it.
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// The `otpauth://` URL authenticator apps scan from a QR code.
+pub fn otpauth_url(secret: &str, issuer: &str, account: &str) -> Result<String, Error> {
+    // Both end up in the label of the URL, which uses ':' as its separator.
+    let totp = totp(
+        secret,
+        Some(issuer.replace(':', "")),
+        account.replace(':', ""),
+    )?;
+
+    Ok(totp.get_url())
+}
+
+/// Checks the code against the secret, allowing for one step of clock drift either way.
+///
+/// # Arguments
+/// * `last_step` - Step of the last accepted code, it and earlier steps are rejected.
+/// # Returns
+/// * `Option<i64>` - The time step the code belongs to, `None` if it doesn't match.
+pub fn verify(secret: &str, code: &str, last_step: Option<i64>) -> Result<Option<i64>, Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    verify_at(secret, code, now, last_step)
+}
+
+fn verify_at(
+    secret: &str,
+    code: &str,
+    time: u64,
+    last_step: Option<i64>,
+) -> Result<Option<i64>, Error> {
+    let totp = totp(secret, None, String::new())?;
+    let code = code.trim();
+    let current = (time / TOTP_STEP) as i64;
+
+    for step in current - 1..=current + 1 {
+        if step < 0 || matches!(last_step, Some(last_step) if step <= last_step) {
+            continue;
+        }
+
+        let expected = totp.generate(step as u64 * TOTP_STEP);
+        if constant_time_eq(expected.as_bytes(), code.as_bytes()) {
+            return Ok(Some(step));
+        }
+    }
+
+    Ok(None)
+}
+
+fn totp(secret: &str, issuer: Option<String>, account: String) -> Result<TOTP, Error> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|err| {
+            error!("Invalid TOTP secret: {err:?}");
+            Error::new("INVALID_TOTP_SECRET", StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        1,
+        TOTP_STEP,
+        secret,
+        issuer,
+        account,
+    )
+    .map_err(|err| {
+        error!("Invalid TOTP parameters: {err:?}");
+        Error::new("INVALID_TOTP_SECRET", StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Base32 of the RFC 6238 test secret "12345678901234567890".
+    const RFC_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn test_verify() {
+        // RFC 6238 appendix B lists 94287082 at T = 59, the last 6 digits are the 6 digit code.
+        assert_eq!(verify_at(RFC_SECRET, "287082", 59, None).unwrap(), Some(1));
+        // One step of drift is fine, more isn't.
+        assert_eq!(verify_at(RFC_SECRET, "287082", 89, None).unwrap(), Some(1));
+        assert_eq!(verify_at(RFC_SECRET, "287082", 120, None).unwrap(), None);
+        // A code can't be used twice.
+        assert_eq!(verify_at(RFC_SECRET, "287082", 59, Some(1)).unwrap(), None);
+        assert_eq!(verify_at(RFC_SECRET, "000000", 59, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_otpauth_url() {
+        let secret = generate_secret();
+        let url = otpauth_url(&secret, "Weeb Music Database", "user:name").unwrap();
+        assert!(url.starts_with("otpauth://totp/Weeb%20Music%20Database:username?secret="));
+        assert!(url.contains(&secret));
+    }
+}